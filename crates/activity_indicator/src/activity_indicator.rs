@@ -679,7 +679,8 @@ impl ActivityIndicator {
 
         // Show any application auto-update info.
         if let Some(updater) = &self.auto_updater {
-            return match &updater.read(cx).status() {
+            let status = updater.read(cx).status();
+            return match &status {
                 AutoUpdateStatus::Checking => Some(Content {
                     icon: Some(
                         Icon::new(IconName::Download)
@@ -692,19 +693,24 @@ impl ActivityIndicator {
                     })),
                     tooltip_message: None,
                 }),
-                AutoUpdateStatus::Downloading { version } => Some(Content {
+                AutoUpdateStatus::Downloading { version, url, .. } => Some(Content {
                     icon: Some(
                         Icon::new(IconName::Download)
                             .size(IconSize::Small)
                             .into_any_element(),
                     ),
-                    message: "Downloading Zed update…".to_string(),
+                    message: match status.progress() {
+                        Some(progress) => {
+                            format!("Downloading Zed update… {:.0}%", progress * 100.0)
+                        }
+                        None => "Downloading Zed update…".to_string(),
+                    },
                     on_click: Some(Arc::new(|this, window, cx| {
                         this.dismiss_error_message(&DismissErrorMessage, window, cx)
                     })),
-                    tooltip_message: Some(Self::version_tooltip_message(&version)),
+                    tooltip_message: Some(Self::update_tooltip_message(&version, &url)),
                 }),
-                AutoUpdateStatus::Installing { version } => Some(Content {
+                AutoUpdateStatus::Installing { version, url } => Some(Content {
                     icon: Some(
                         Icon::new(IconName::Download)
                             .size(IconSize::Small)
@@ -714,7 +720,7 @@ impl ActivityIndicator {
                     on_click: Some(Arc::new(|this, window, cx| {
                         this.dismiss_error_message(&DismissErrorMessage, window, cx)
                     })),
-                    tooltip_message: Some(Self::version_tooltip_message(&version)),
+                    tooltip_message: Some(Self::update_tooltip_message(&version, &url)),
                 }),
                 AutoUpdateStatus::Updated {
                     binary_path,
@@ -730,19 +736,42 @@ impl ActivityIndicator {
                     })),
                     tooltip_message: Some(Self::version_tooltip_message(&version)),
                 }),
-                AutoUpdateStatus::Errored => Some(Content {
+                AutoUpdateStatus::Paused { version, .. } => Some(Content {
+                    icon: Some(
+                        Icon::new(IconName::Download)
+                            .size(IconSize::Small)
+                            .into_any_element(),
+                    ),
+                    message: "Zed update paused, click to resume".to_string(),
+                    on_click: Some(Arc::new(|this, _, cx| {
+                        if let Some(updater) = this.auto_updater.clone() {
+                            updater.update(cx, |updater, cx| updater.resume(cx));
+                        }
+                    })),
+                    tooltip_message: Some(Self::version_tooltip_message(&version)),
+                }),
+                AutoUpdateStatus::ReadyToInstallOnQuit { version, .. } => Some(Content {
+                    icon: None,
+                    message: "Zed update ready, will install on quit".to_string(),
+                    on_click: None,
+                    tooltip_message: Some(Self::version_tooltip_message(&version)),
+                }),
+                AutoUpdateStatus::Errored { error, kind } => Some(Content {
                     icon: Some(
                         Icon::new(IconName::Warning)
                             .size(IconSize::Small)
                             .into_any_element(),
                     ),
-                    message: "Auto update failed".to_string(),
+                    message: match kind {
+                        Some(kind) => format!("Auto update failed: {}", kind.user_message()),
+                        None => "Auto update failed".to_string(),
+                    },
                     on_click: Some(Arc::new(|this, window, cx| {
                         this.dismiss_error_message(&DismissErrorMessage, window, cx)
                     })),
-                    tooltip_message: None,
+                    tooltip_message: Some(format!("{error:#}")),
                 }),
-                AutoUpdateStatus::Idle => None,
+                AutoUpdateStatus::Idle | AutoUpdateStatus::UpToDate { .. } => None,
             };
         }
 
@@ -769,14 +798,14 @@ impl ActivityIndicator {
     }
 
     fn version_tooltip_message(version: &VersionCheckType) -> String {
-        format!("Version: {}", {
-            match version {
-                auto_update::VersionCheckType::Sha(sha) => format!("{}…", sha.short()),
-                auto_update::VersionCheckType::Semantic(semantic_version) => {
-                    semantic_version.to_string()
-                }
-            }
-        })
+        match version {
+            VersionCheckType::Sha(_) => format!("Version: {version}…"),
+            VersionCheckType::Semantic(_) => format!("Version: {version}"),
+        }
+    }
+
+    fn update_tooltip_message(version: &VersionCheckType, url: &str) -> String {
+        format!("{}\nFrom: {url}", Self::version_tooltip_message(version))
     }
 
     fn toggle_language_server_work_context_menu(
@@ -2845,7 +2845,7 @@ impl Thread {
                 thread_data,
                 final_project_snapshot
             );
-            client.telemetry().flush_events().await;
+            client.telemetry().flush_events().await.log_err();
 
             Ok(())
         })
@@ -2890,7 +2890,7 @@ impl Thread {
                     thread_data,
                     final_project_snapshot
                 );
-                client.telemetry().flush_events().await;
+                client.telemetry().flush_events().await.log_err();
 
                 Ok(())
             })
@@ -3168,7 +3168,7 @@ impl Thread {
                             github_login = github_login
                         );
 
-                        client.telemetry().flush_events().await;
+                        client.telemetry().flush_events().await.log_err();
                     }
                 }
             })
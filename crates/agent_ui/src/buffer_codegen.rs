@@ -549,6 +549,7 @@ impl CodegenAlternative {
                 let (mut diff_tx, mut diff_rx) = mpsc::channel(1);
                 let executor = cx.background_executor().clone();
                 let message_id = message_id.clone();
+                let token_usage = token_usage.clone();
                 let line_based_stream_diff: Task<anyhow::Result<()>> =
                     cx.background_spawn(async move {
                         let mut response_latency = None;
@@ -650,6 +651,9 @@ impl CodegenAlternative {
                         let result = diff.await;
 
                         let error_message = result.as_ref().err().map(|error| error.to_string());
+                        let usage = token_usage
+                            .map(|usage| *usage.lock())
+                            .filter(|usage| usage.total_tokens() > 0);
                         report_assistant_event(
                             AssistantEventData {
                                 conversation_id: None,
@@ -661,6 +665,9 @@ impl CodegenAlternative {
                                 response_latency,
                                 error_message,
                                 language_name: language_name.map(|name| name.to_proto()),
+                                input_tokens: usage.map(|usage| usage.input_tokens),
+                                output_tokens: usage.map(|usage| usage.output_tokens),
+                                cost_usd: None,
                             },
                             telemetry,
                             http_client,
@@ -476,6 +476,9 @@ impl InlineAssistant {
                     response_latency: None,
                     error_message: None,
                     language_name: buffer.language().map(|language| language.name().to_proto()),
+                    input_tokens: None,
+                    output_tokens: None,
+                    cost_usd: None,
                 });
             }
         }
@@ -1072,6 +1075,9 @@ impl InlineAssistant {
                         response_latency: None,
                         error_message: None,
                         language_name: language_name.map(|name| name.to_proto()),
+                        input_tokens: None,
+                        output_tokens: None,
+                        cost_usd: None,
                     },
                     Some(self.telemetry.clone()),
                     cx.http_client(),
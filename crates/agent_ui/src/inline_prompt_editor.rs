@@ -334,12 +334,14 @@ impl<T: 'static> PromptEditor<T> {
             EditorEvent::Edited { .. } => {
                 if let Some(workspace) = window.root::<Workspace>().flatten() {
                     workspace.update(cx, |workspace, cx| {
-                        let is_via_ssh = workspace.project().read(cx).is_via_ssh();
-
-                        workspace
-                            .client()
-                            .telemetry()
-                            .log_edit_event("inline assist", is_via_ssh);
+                        let project = workspace.project().read(cx);
+                        let is_via_ssh = project.is_via_ssh();
+                        let telemetry = workspace.client().telemetry();
+                        let git_branch = telemetry
+                            .include_git_branch()
+                            .then(|| project.active_git_branch(cx))
+                            .flatten();
+                        telemetry.log_edit_event("inline assist", is_via_ssh, git_branch);
                     });
                 }
                 let prompt = self.editor.read(cx).text(cx);
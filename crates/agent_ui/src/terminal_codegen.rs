@@ -54,6 +54,10 @@ impl TerminalCodegen {
                     .as_ref()
                     .ok()
                     .and_then(|response| response.message_id.clone());
+                let token_usage = response
+                    .as_ref()
+                    .ok()
+                    .map(|response| response.last_token_usage.clone());
 
                 let (mut hunks_tx, mut hunks_rx) = mpsc::channel(1);
 
@@ -79,6 +83,9 @@ impl TerminalCodegen {
                         let result = task.await;
 
                         let error_message = result.as_ref().err().map(|error| error.to_string());
+                        let usage = token_usage
+                            .map(|usage| *usage.lock())
+                            .filter(|usage| usage.total_tokens() > 0);
                         report_assistant_event(
                             AssistantEventData {
                                 conversation_id: None,
@@ -90,6 +97,9 @@ impl TerminalCodegen {
                                 response_latency,
                                 error_message,
                                 language_name: None,
+                                input_tokens: usage.map(|usage| usage.input_tokens),
+                                output_tokens: usage.map(|usage| usage.output_tokens),
+                                cost_usd: None,
                             },
                             telemetry,
                             http_client,
@@ -341,6 +341,9 @@ impl TerminalInlineAssistant {
                         response_latency: None,
                         error_message: None,
                         language_name: None,
+                        input_tokens: None,
+                        output_tokens: None,
+                        cost_usd: None,
                     },
                     codegen.telemetry.clone(),
                     cx.http_client(),
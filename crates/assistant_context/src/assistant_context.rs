@@ -2048,6 +2048,7 @@ impl AssistantContext {
                 let stream = model.stream_completion(request, &cx);
                 let assistant_message_id = assistant_message.id;
                 let mut response_latency = None;
+                let mut token_usage = None;
                 let stream_completion = async {
                     let request_start = Instant::now();
                     let mut events = stream.await?;
@@ -2062,6 +2063,9 @@ impl AssistantContext {
                             response_latency = Some(request_start.elapsed());
                         }
                         let event = event?;
+                        if let LanguageModelCompletionEvent::UsageUpdate(usage) = &event {
+                            token_usage = Some(*usage);
+                        }
 
                         let mut context_event = None;
                         let mut thought_process_output_section = None;
@@ -2227,6 +2231,9 @@ impl AssistantContext {
                             response_latency,
                             error_message,
                             language_name: language_name.map(|name| name.to_proto()),
+                            input_tokens: token_usage.map(|usage| usage.input_tokens),
+                            output_tokens: token_usage.map(|usage| usage.output_tokens),
+                            cost_usd: None,
                         },
                         this.telemetry.clone(),
                         cx.http_client(),
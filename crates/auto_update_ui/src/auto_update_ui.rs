@@ -1,4 +1,4 @@
-use auto_update::AutoUpdater;
+use auto_update::{AutoUpdater, VersionCheckType};
 use client::proto::UpdateNotification;
 use editor::{Editor, MultiBuffer};
 use gpui::{App, Context, DismissEvent, Entity, Window, actions, prelude::*};
@@ -141,23 +141,31 @@ pub fn notify_if_app_was_updated(cx: &mut App) {
         return;
     }
 
-    let should_show_notification = updater.read(cx).should_show_update_notification(cx);
+    let should_show_notification = updater.read(cx).should_show_update_notification_for(cx);
     cx.spawn(async move |cx| {
-        let should_show_notification = should_show_notification.await?;
-        if should_show_notification {
-            cx.update(|cx| {
-                let version = updater.read(cx).current_version();
-                let app_name = ReleaseChannel::global(cx).display_name();
-                show_app_notification(
-                    NotificationId::unique::<UpdateNotification>(),
-                    cx,
-                    move |cx| {
-                        let workspace_handle = cx.entity().downgrade();
-                        cx.new(|cx| {
-                            MessageNotification::new(
-                                format!("Updated to {app_name} {}", version),
-                                cx,
-                            )
+        let Some(version) = should_show_notification.await? else {
+            return anyhow::Ok(());
+        };
+        cx.update(|cx| {
+            let current_version = VersionCheckType::Semantic(updater.read(cx).current_version());
+            // The user has since updated past the version this notification was about.
+            if current_version.is_newer_than(&version) == Some(true) {
+                updater.update(cx, |updater, cx| {
+                    updater
+                        .set_should_show_update_notification(None, cx)
+                        .detach_and_log_err(cx);
+                });
+                return;
+            }
+
+            let app_name = ReleaseChannel::global(cx).display_name();
+            show_app_notification(
+                NotificationId::unique::<UpdateNotification>(),
+                cx,
+                move |cx| {
+                    let workspace_handle = cx.entity().downgrade();
+                    cx.new(|cx| {
+                        MessageNotification::new(format!("Updated to {app_name} {}", version), cx)
                             .primary_message("View Release Notes")
                             .primary_on_click(move |window, cx| {
                                 if let Some(workspace) = workspace_handle.upgrade() {
@@ -168,16 +176,15 @@ pub fn notify_if_app_was_updated(cx: &mut App) {
                                 cx.emit(DismissEvent);
                             })
                             .show_suppress_button(false)
-                        })
-                    },
-                );
-                updater.update(cx, |updater, cx| {
-                    updater
-                        .set_should_show_update_notification(false, cx)
-                        .detach_and_log_err(cx);
-                })
-            })?;
-        }
+                    })
+                },
+            );
+            updater.update(cx, |updater, cx| {
+                updater
+                    .set_should_show_update_notification(None, cx)
+                    .detach_and_log_err(cx);
+            })
+        })?;
         anyhow::Ok(())
     })
     .detach();
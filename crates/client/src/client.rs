@@ -90,18 +90,25 @@ actions!(
         /// Signs out of Zed account.
         SignOut,
         /// Reconnects to the collaboration server.
-        Reconnect
+        Reconnect,
+        /// Sends queued telemetry events immediately, rather than waiting for the flush interval.
+        FlushTelemetry
     ]
 );
 
 #[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ClientSettingsContent {
     server_url: Option<String>,
+    /// The only hosts that telemetry and update requests may be sent to. When empty, any host
+    /// is allowed.
+    allowed_hosts: Option<Vec<String>>,
 }
 
 #[derive(Deserialize)]
 pub struct ClientSettings {
     pub server_url: String,
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
 }
 
 impl Settings for ClientSettings {
@@ -120,6 +127,23 @@ impl Settings for ClientSettings {
     fn import_from_vscode(_vscode: &settings::VsCodeSettings, _current: &mut Self::FileContent) {}
 }
 
+/// Refuses `url` unless its host is in `allowed_hosts`, or `allowed_hosts` is empty, so that
+/// callers can check a request before it's sent. Hosts must match exactly, ignoring case.
+pub fn ensure_host_allowed(url: &str, allowed_hosts: &[String]) -> Result<()> {
+    if allowed_hosts.is_empty() {
+        return Ok(());
+    }
+    let url = Url::parse(url).context("invalid request URL")?;
+    let host = url.host_str().context("request URL has no host")?;
+    anyhow::ensure!(
+        allowed_hosts
+            .iter()
+            .any(|allowed_host| allowed_host.eq_ignore_ascii_case(host)),
+        "refusing to send a request to {host}, as it isn't in `allowed_hosts`"
+    );
+    Ok(())
+}
+
 #[derive(Default, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProxySettingsContent {
     proxy: Option<String>,
@@ -191,6 +215,22 @@ pub fn init(client: &Arc<Client>, cx: &mut App) {
             }
         }
     });
+
+    cx.on_action({
+        let client = client.clone();
+        move |_: &FlushTelemetry, cx| {
+            if let Some(client) = client.upgrade() {
+                let flush = client.telemetry().flush_events();
+                cx.background_spawn(async move {
+                    match flush.await {
+                        Ok(event_count) => log::info!("flushed {event_count} telemetry events"),
+                        Err(error) => log::error!("failed to flush telemetry events: {error:?}"),
+                    }
+                })
+                .detach();
+            }
+        }
+    });
 }
 
 pub type MessageToClientHandler = Box<dyn Fn(&MessageToClient, &mut App) + Send + Sync + 'static>;
@@ -502,10 +542,28 @@ impl<T: 'static> Drop for PendingEntitySubscription<T> {
     }
 }
 
-#[derive(Copy, Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct TelemetrySettings {
     pub diagnostics: bool,
     pub metrics: bool,
+    pub flush_interval_secs: Option<u64>,
+    pub max_queue_len: Option<usize>,
+    pub max_batch_bytes: Option<usize>,
+    pub telemetry_endpoint: Option<String>,
+    pub compress_telemetry: bool,
+    pub crash_reports: bool,
+    pub project_type_scan_cooldown_secs: Option<u64>,
+    pub sample_rate: f32,
+    pub max_log_bytes: Option<u64>,
+    pub dedup_window: Option<usize>,
+    pub require_checksum: bool,
+    pub user_agent: Option<String>,
+    pub persist_project_events: bool,
+    pub include_session_id: bool,
+    pub require_consent: bool,
+    pub environment_tag: Option<String>,
+    pub server_accepts_gzip: Option<bool>,
+    pub include_git_branch: bool,
 }
 
 /// Control what info is collected by Zed.
@@ -515,10 +573,99 @@ pub struct TelemetrySettingsContent {
     ///
     /// Default: true
     pub diagnostics: Option<bool>,
-    /// Send anonymized usage data like what languages you're using Zed with.
+    /// Send anonymized usage data like what languages you're using Zed with. Project settings
+    /// can turn this off for events about that project, taking precedence over user settings.
     ///
     /// Default: true
     pub metrics: Option<bool>,
+    /// How often, in seconds, queued events are sent.
+    ///
+    /// Default: 1 in debug builds, 300 otherwise
+    pub flush_interval_secs: Option<u64>,
+    /// How many events can be queued before they are sent early.
+    ///
+    /// Default: 5 in debug builds, 50 otherwise
+    pub max_queue_len: Option<usize>,
+    /// The size, in bytes, of the events sent in a single request. Larger batches are split
+    /// across several requests.
+    ///
+    /// Default: 1048576 (1 MiB)
+    pub max_batch_bytes: Option<usize>,
+    /// The full URL to send events to, for running your own collector.
+    ///
+    /// Default: null (use the Zed API)
+    pub telemetry_endpoint: Option<String>,
+    /// Gzip large batches of events before sending them. Disable this if your
+    /// collector doesn't understand `Content-Encoding: gzip`.
+    ///
+    /// Default: true
+    pub compress_telemetry: Option<bool>,
+    /// Upload crash dumps to the configured minidump endpoint. This is independent
+    /// of `diagnostics` and `metrics`, so crash reporting can be enabled on its own.
+    ///
+    /// Default: false
+    pub crash_reports: Option<bool>,
+    /// How long, in seconds, to wait before rescanning a worktree in which no
+    /// project type was detected, unless new top-level entries appear.
+    ///
+    /// Default: 60
+    pub project_type_scan_cooldown_secs: Option<u64>,
+    /// The fraction of high-frequency events, like edits, to send, from 0.0 to 1.0.
+    /// Assistant and project events are always sent.
+    ///
+    /// Default: 1.0
+    pub sample_rate: Option<f32>,
+    /// The size, in bytes, above which `telemetry.log` is rotated to `telemetry.log.1`.
+    ///
+    /// Default: 5242880 (5 MiB)
+    pub max_log_bytes: Option<u64>,
+    /// How many recent events to compare new events against, skipping exact repeats.
+    /// Edit events are never skipped. Set to 0 to disable.
+    ///
+    /// Default: 16
+    pub dedup_window: Option<usize>,
+    /// Send an empty `x-zed-checksum` header when no checksum seed is configured. Disable
+    /// this if your collector rejects empty checksums, to leave the header out instead.
+    ///
+    /// Default: true
+    pub require_checksum: Option<bool>,
+    /// The `User-Agent` header to send with telemetry and update requests, for proxies that
+    /// route on it.
+    ///
+    /// Default: null (e.g. "Fred/1.2.3 (macOS; aarch64)")
+    pub user_agent: Option<String>,
+    /// Remember which projects were reported as opened today, so that restarting doesn't
+    /// report them again. Otherwise, each session reports the projects it opens.
+    ///
+    /// Default: false
+    pub persist_project_events: Option<bool>,
+    /// Send the ID generated for each launch with events, which relates the events from a
+    /// single session.
+    ///
+    /// Default: true
+    pub include_session_id: Option<bool>,
+    /// Hold back telemetry until the user has been asked whether to allow it, dropping it if
+    /// they decline.
+    ///
+    /// Default: false
+    pub require_consent: Option<bool>,
+    /// A label sent with every event, such as "ci" or "staging", for filtering out telemetry
+    /// from machines that aren't in regular use.
+    ///
+    /// Default: null
+    pub environment_tag: Option<String>,
+    /// Whether the collector accepts gzipped events. When null, a custom `telemetry_endpoint`
+    /// is asked once per session, and batches are sent uncompressed unless it advertises gzip
+    /// in an `Accept-Encoding` response header.
+    ///
+    /// Default: null
+    pub server_accepts_gzip: Option<bool>,
+    /// Add the current git branch to events about a worktree, such as "Project Opened". The
+    /// branch is read from the worktree's repository on disk, and is left out for worktrees
+    /// that aren't in a git repository.
+    ///
+    /// Default: false
+    pub include_git_branch: Option<bool>,
 }
 
 impl settings::Settings for TelemetrySettings {
@@ -2190,6 +2337,21 @@ mod tests {
         subscription: Option<Subscription>,
     }
 
+    #[gpui::test]
+    async fn test_ensure_installation_id(cx: &mut TestAppContext) {
+        init_test(cx);
+        let http_client = FakeHttpClient::with_200_response();
+        let client =
+            cx.update(|cx| Client::new(Arc::new(FakeSystemClock::new()), http_client.clone(), cx));
+
+        let installation_id = client.telemetry().ensure_installation_id().await;
+        assert!(uuid::Uuid::parse_str(&installation_id).is_ok());
+        assert_eq!(
+            client.telemetry().ensure_installation_id().await,
+            installation_id
+        );
+    }
+
     fn init_test(cx: &mut TestAppContext) {
         cx.update(|cx| {
             let settings_store = SettingsStore::test(cx);
@@ -1,34 +1,85 @@
 mod event_coalescer;
 
-use crate::TelemetrySettings;
-use anyhow::Result;
+use crate::{ClientSettings, TelemetrySettings, ensure_host_allowed};
+use anyhow::{Context as _, Result};
 use clock::SystemClock;
+use db::kvp::KEY_VALUE_STORE;
+use flate2::{Compression, write::GzEncoder};
 use futures::channel::mpsc;
-use futures::{Future, FutureExt, StreamExt};
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt as _, StreamExt};
 use gpui::{App, AppContext as _, BackgroundExecutor, Task};
 use http_client::{self, AsyncBody, HttpClient, HttpClientWithUrl, Method, Request};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, MutexGuard};
+use rand::{Rng, SeedableRng as _, rngs::StdRng};
 use regex::Regex;
 use release_channel::ReleaseChannel;
+use serde::Serialize;
 use settings::{Settings, SettingsStore};
 use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::Write;
 use std::sync::LazyLock;
 use std::time::Instant;
-use std::{env, mem, path::PathBuf, sync::Arc, time::Duration};
-use telemetry_events::{AssistantEventData, AssistantPhase, Event, EventRequestBody, EventWrapper};
-use util::{ResultExt, TryFutureExt};
-use worktree::{UpdatedEntriesSet, WorktreeId};
+use std::{
+    env, mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use telemetry_events::{
+    AssistantEventData, AssistantPhase, Event, EventRequestBody, EventWrapper, FlexibleEvent,
+};
+use util::ResultExt;
+use uuid::Uuid;
+use worktree::{PathChange, UpdatedEntriesSet, WorktreeId};
 
-use self::event_coalescer::EventCoalescer;
+use self::event_coalescer::{AssistantResponseCoalescer, AssistantResponsePeriod, EventCoalescer};
 
 pub struct Telemetry {
     clock: Arc<dyn SystemClock>,
     http_client: Arc<HttpClientWithUrl>,
     executor: BackgroundExecutor,
     state: Arc<Mutex<TelemetryState>>,
+    /// Held while writing the spool, which happens without holding `state`.
+    spool_lock: futures::lock::Mutex<()>,
+    /// Set from [`NO_TELEMETRY_ENV_VAR`]; turns every method into a no-op.
+    disabled: bool,
+}
+
+/// A snapshot of how many events have passed through the telemetry queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TelemetryStats {
+    /// Events reported since startup, whether or not they were queued.
+    pub events_recorded: usize,
+    /// Events included in a batch handed off to be sent.
+    pub events_flushed: usize,
+    /// Events discarded without being sent, e.g. because metrics are disabled.
+    pub events_dropped: usize,
+    /// Events currently waiting in the queue.
+    pub events_queued: usize,
+}
+
+/// Telemetry's configuration and live state, e.g. for a diagnostics panel or a support dump.
+#[derive(Clone, Debug, Serialize)]
+pub struct TelemetrySnapshot {
+    pub settings: TelemetrySettings,
+    /// Whether telemetry was turned off entirely by [`NO_TELEMETRY_ENV_VAR`].
+    pub disabled: bool,
+    /// Whether this build has a seed to checksum requests with.
+    pub has_checksum_seed: bool,
+    /// The stored consent decision, `None` until the user has made one.
+    pub consent: Option<bool>,
+    /// Events held back until the user decides whether to allow telemetry.
+    pub events_awaiting_consent: usize,
+    /// When events were last sent successfully, since startup. Not serialized, as it's only
+    /// meaningful within this process.
+    #[serde(skip)]
+    pub last_flushed_at: Option<Instant>,
+    /// The event counts, including the length of the queue.
+    pub stats: TelemetryStats,
 }
 
 struct TelemetryState {
@@ -37,20 +88,79 @@ struct TelemetryState {
     installation_id: Option<Arc<str>>, // Per app installation (different for dev, nightly, preview, and stable)
     session_id: Option<String>,        // Per app launch
     metrics_id: Option<Arc<str>>,      // Per logged-in user
+    /// Lets events be sent without an installation ID, set with [`Telemetry::set_anonymous`].
+    anonymous: bool,
     release_channel: Option<&'static str>,
     architecture: &'static str,
     events_queue: Vec<EventWrapper>,
     flush_events_task: Option<Task<()>>,
+    /// Set when the collector asked us to back off, until which queued events are held.
+    retry_flush_at: Option<Instant>,
     log_file: Option<File>,
     is_staff: Option<bool>,
     first_event_date_time: Option<Instant>,
+    /// The timestamp given to the most recent event, which later events never precede.
+    last_event_date_time: Option<Instant>,
     event_coalescer: EventCoalescer,
+    last_edit_is_via_ssh: bool,
+    /// The branch of the most recent edit, when the `include_git_branch` setting is on.
+    last_edit_git_branch: Option<String>,
+    assistant_response_coalescer: AssistantResponseCoalescer,
     max_queue_size: usize,
-    worktrees_with_project_type_events_sent: HashSet<WorktreeId>,
+    max_batch_bytes: usize,
+    flush_interval: Duration,
+    telemetry_endpoint: Option<String>,
+    /// From the `allowed_hosts` setting.
+    allowed_hosts: Vec<String>,
+    compress_telemetry: bool,
+    /// Whether the custom telemetry endpoint advertised gzip support, once it's been asked.
+    endpoint_accepts_gzip: Option<bool>,
+    require_checksum: bool,
+    user_agent: Option<String>,
+    events_recorded: usize,
+    events_flushed: usize,
+    events_dropped: usize,
+    worktrees_with_project_type_events_sent: HashMap<WorktreeId, HashSet<(&'static str, String)>>,
+    /// Names of the extensions already reported as loaded this session.
+    extension_loaded_events_sent: HashSet<String>,
+    worktree_opened_at: HashMap<WorktreeId, Instant>,
+    worktrees_scanned_without_project_type: HashMap<WorktreeId, Instant>,
+    project_type_scan_cooldown: Duration,
+    /// Consulted in order for files that no built-in marker file name matches.
+    project_type_detectors: Vec<(Regex, &'static str)>,
+    sampling_rng: StdRng,
+    max_log_bytes: u64,
+    recent_events: RecentEventFilter,
+    reported_settings: HashMap<&'static str, serde_json::Value>,
+    /// Worktrees whose project settings turn metrics off, overriding the global setting.
+    worktrees_with_metrics_disabled: HashSet<WorktreeId>,
+    project_event_store: Arc<dyn ProjectEventStore>,
+    /// The day each persisted "Project Opened" key was last reported on, or `None` while they're
+    /// being loaded from `project_event_store`.
+    project_opened_dates: Option<HashMap<String, String>>,
+    /// A reading of the telemetry clock and the UTC time it was taken at, so that the days
+    /// "Project Opened" events are deduplicated per follow the telemetry clock.
+    utc_anchor: (Instant, chrono::DateTime<chrono::Utc>),
+    /// Project types discovered while `project_opened_dates` was loading.
+    pending_project_types: Vec<PendingProjectTypes>,
+    /// The stored consent decision, `None` until the user has made one.
+    consent: Option<bool>,
+    /// Events reported while `require_consent` is on and no decision has been made yet.
+    events_awaiting_consent: Vec<(Event, Option<WorktreeId>)>,
+    /// Queued events are also appended here, so that they can be sent after a crash.
+    spool_path: Option<PathBuf>,
+    /// Lines for events queued since the spool was last written, appended in the background.
+    spool_buffer: Vec<u8>,
+    spool_append_scheduled: bool,
+    last_flushed_at: Option<Instant>,
+    flush_circuit: FlushCircuit,
+    #[cfg(any(test, feature = "test-support"))]
+    memory_sink: Option<Vec<Event>>,
 
     os_name: String,
     app_version: String,
     os_version: Option<String>,
+    os_version_probed_at: Option<Instant>,
 }
 
 #[cfg(debug_assertions)]
@@ -64,13 +174,99 @@ const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
 #[cfg(not(debug_assertions))]
 const FLUSH_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+const FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT: usize = 5;
+
+const FLUSH_CIRCUIT_COOLDOWN: Duration = Duration::from_secs(60 * 10);
+
+const PROJECT_TYPE_SCAN_COOLDOWN: Duration = Duration::from_secs(60);
+
+const OS_VERSION_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Shared with the ID that `zed` generates at startup, so both refer to the same installation.
+const INSTALLATION_ID_KEY: &str = "installation_id";
+const PROJECT_OPENED_KEY_PREFIX: &str = "telemetry_project_opened_";
+const CONSENT_KEY: &str = "telemetry_consent";
+const FIRST_RUN_KEY: &str = "telemetry_first_run_reported";
+
+/// Remembers when projects were last reported as opened, so that "Project Opened" events can
+/// be deduplicated across restarts, and the user's telemetry consent decision.
+pub trait ProjectEventStore: Send + Sync {
+    fn read(&self, key: &str) -> Option<String>;
+    /// The entries whose keys start with `prefix`.
+    fn scan(&self, prefix: &str) -> BoxFuture<'static, Result<Vec<(String, String)>>>;
+    fn write(&self, key: String, value: String) -> BoxFuture<'static, Result<()>>;
+    fn delete(&self, key: String) -> BoxFuture<'static, Result<()>>;
+}
+
+struct KeyValueProjectEventStore;
+
+impl ProjectEventStore for KeyValueProjectEventStore {
+    fn read(&self, key: &str) -> Option<String> {
+        KEY_VALUE_STORE.read_kvp(key).log_err().flatten()
+    }
+
+    fn scan(&self, prefix: &str) -> BoxFuture<'static, Result<Vec<(String, String)>>> {
+        let prefix = prefix.to_string();
+        async move { KEY_VALUE_STORE.scan_kvp(prefix).await }.boxed()
+    }
+
+    fn write(&self, key: String, value: String) -> BoxFuture<'static, Result<()>> {
+        async move { KEY_VALUE_STORE.write_kvp(key, value).await }.boxed()
+    }
+
+    fn delete(&self, key: String) -> BoxFuture<'static, Result<()>> {
+        async move { KEY_VALUE_STORE.delete_kvp(key).await }.boxed()
+    }
+}
+
+struct PendingProjectTypes {
+    worktree_id: WorktreeId,
+    worktree_abs_path: PathBuf,
+    git_branch: Option<String>,
+    project_types: Vec<(&'static str, String)>,
+}
+
+const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+
+const MAX_BATCH_BYTES: usize = 1024 * 1024;
+
+const MAX_LOG_BACKUPS: usize = 3;
+
+const DEDUP_WINDOW: usize = 16;
+
+/// The longest duration reported between two event timestamps.
+const MAX_EVENT_DURATION: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// User settings whose changes are reported. Only the key is ever sent, never the value.
+const REPORTED_SETTINGS: &[&str] = &[
+    "auto_update",
+    "base_keymap",
+    "buffer_font_size",
+    "format_on_save",
+    "helix_mode",
+    "relative_line_numbers",
+    "soft_wrap",
+    "tab_size",
+    "theme",
+    "ui_font_size",
+    "vim_mode",
+];
+
+/// Events whose repeats are meaningful, so are never skipped as duplicates.
+const DEDUP_EXEMPT_EVENT_TYPES: &[&str] = &["Editor Edited", "Settings Changed"];
+
 static ZED_CLIENT_CHECKSUM_SEED: LazyLock<Option<Vec<u8>>> = LazyLock::new(|| {
     option_env!("ZED_CLIENT_CHECKSUM_SEED")
-        .map(|s| s.as_bytes().into())
+        .and_then(parse_checksum_seed)
         .or_else(|| {
             env::var("ZED_CLIENT_CHECKSUM_SEED")
                 .ok()
-                .map(|s| s.as_bytes().into())
+                .and_then(|s| parse_checksum_seed(&s))
         })
 });
 
@@ -80,11 +276,31 @@ pub static MINIDUMP_ENDPOINT: LazyLock<Option<String>> = LazyLock::new(|| {
         .or_else(|| env::var("ZED_MINIDUMP_ENDPOINT").ok())
 });
 
+/// When set to anything other than an empty string or `0`, disables all telemetry regardless of
+/// settings.
+pub const NO_TELEMETRY_ENV_VAR: &str = "FRED_NO_TELEMETRY";
+
+/// Set by packagers to report how Fred was installed, e.g. `homebrew` or `direct`. Otherwise
+/// the source is guessed from the executable's path.
+pub const INSTALL_SOURCE_ENV_VAR: &str = "FRED_INSTALL_SOURCE";
+
+/// Request bodies smaller than this aren't worth the overhead of compressing.
+const COMPRESSION_THRESHOLD_BYTES: usize = 1024;
+
+const CHECKSUM_SELF_TEST_SEED: &[u8] = b"fred-self-test";
+const CHECKSUM_SELF_TEST_INPUT: &[u8] = br#"{"events":[]}"#;
+const CHECKSUM_SELF_TEST_EXPECTED: &str =
+    "bbad3b165d5144db292f8bbe79f258a56f176db76829766e59c0c6b1bd6d6f8e";
+
 static DOTNET_PROJECT_FILES_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"^(global\.json|Directory\.Build\.props|.*\.(csproj|fsproj|vbproj|sln))$").unwrap()
 });
 
-pub fn os_name() -> String {
+static TERRAFORM_PROJECT_FILES_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^.*\.tf$").unwrap());
+
+// The compositor can't change while we're running, so there's no need to probe for it again.
+static OS_NAME: LazyLock<String> = LazyLock::new(|| {
     #[cfg(target_os = "macos")]
     {
         "macOS".to_string()
@@ -102,6 +318,10 @@ pub fn os_name() -> String {
     {
         "Windows".to_string()
     }
+});
+
+pub fn os_name() -> String {
+    OS_NAME.clone()
 }
 
 /// Note: This might do blocking IO! Only call from background threads
@@ -124,8 +344,6 @@ pub fn os_version() -> String {
     }
     #[cfg(any(target_os = "linux", target_os = "freebsd"))]
     {
-        use std::path::Path;
-
         let content = if let Ok(file) = std::fs::read_to_string(&Path::new("/etc/os-release")) {
             file
         } else if let Ok(file) = std::fs::read_to_string(&Path::new("/usr/lib/os-release")) {
@@ -138,18 +356,8 @@ pub fn os_version() -> String {
             );
             "".to_string()
         };
-        let mut name = "unknown";
-        let mut version = "unknown";
 
-        for line in content.lines() {
-            match line.split_once('=') {
-                Some(("ID", val)) => name = val.trim_matches('"'),
-                Some(("VERSION_ID", val)) => version = val.trim_matches('"'),
-                _ => {}
-            }
-        }
-
-        format!("{} {}", name, version)
+        parse_os_release(&content)
     }
 
     #[cfg(target_os = "windows")]
@@ -169,11 +377,106 @@ pub fn os_version() -> String {
     }
 }
 
+#[cfg(any(target_os = "linux", target_os = "freebsd", test))]
+fn parse_os_release(content: &str) -> String {
+    let mut name = None;
+    let mut version = None;
+    let mut codename = None;
+    let mut pretty_name = None;
+
+    for line in content.lines() {
+        let Some((key, val)) = line.split_once('=') else {
+            continue;
+        };
+        let val = val.trim_matches('"');
+        if val.is_empty() {
+            continue;
+        }
+        match key {
+            "ID" => name = Some(val),
+            "VERSION_ID" => version = Some(val),
+            "VERSION_CODENAME" => codename = Some(val),
+            "PRETTY_NAME" => pretty_name = Some(val),
+            _ => {}
+        }
+    }
+
+    // Rolling distros (e.g. Arch) don't set VERSION_ID, so PRETTY_NAME is the best we have.
+    match (version, codename, pretty_name) {
+        (Some(version), Some(codename), _) => {
+            format!("{} {} ({})", name.unwrap_or("unknown"), version, codename)
+        }
+        (Some(version), None, _) => format!("{} {}", name.unwrap_or("unknown"), version),
+        (None, _, Some(pretty_name)) => pretty_name.to_string(),
+        (None, _, None) => format!("{} unknown", name.unwrap_or("unknown")),
+    }
+}
+
+impl TelemetryState {
+    fn stats(&self) -> TelemetryStats {
+        TelemetryStats {
+            events_recorded: self.events_recorded,
+            events_flushed: self.events_flushed,
+            events_dropped: self.events_dropped,
+            events_queued: self.events_queue.len(),
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &TelemetrySettings) {
+        self.flush_interval = settings
+            .flush_interval_secs
+            .map(Duration::from_secs)
+            .unwrap_or(FLUSH_INTERVAL);
+        self.max_queue_size = settings.max_queue_len.unwrap_or(MAX_QUEUE_LEN);
+        self.max_batch_bytes = settings.max_batch_bytes.unwrap_or(MAX_BATCH_BYTES);
+        if self.telemetry_endpoint != settings.telemetry_endpoint {
+            self.endpoint_accepts_gzip = None;
+        }
+        self.telemetry_endpoint = settings.telemetry_endpoint.clone();
+        self.compress_telemetry = settings.compress_telemetry;
+        self.settings.server_accepts_gzip = settings.server_accepts_gzip;
+        self.require_checksum = settings.require_checksum;
+        // Unlike diagnostics and metrics, crash reporting is an explicit opt-in that Fred honors.
+        self.settings.crash_reports = settings.crash_reports;
+        self.project_type_scan_cooldown = settings
+            .project_type_scan_cooldown_secs
+            .map(Duration::from_secs)
+            .unwrap_or(PROJECT_TYPE_SCAN_COOLDOWN);
+        self.settings.sample_rate = settings.sample_rate;
+        self.max_log_bytes = settings.max_log_bytes.unwrap_or(MAX_LOG_BYTES);
+        self.recent_events.window = settings.dedup_window.unwrap_or(DEDUP_WINDOW);
+        self.user_agent = settings.user_agent.clone();
+        self.settings.persist_project_events = settings.persist_project_events;
+        self.settings.include_session_id = settings.include_session_id;
+        self.settings.require_consent = settings.require_consent;
+        self.settings.include_git_branch = settings.include_git_branch;
+        self.settings.environment_tag = settings
+            .environment_tag
+            .clone()
+            .filter(|tag| !tag.is_empty());
+    }
+}
+
 impl Telemetry {
     pub fn new(
         clock: Arc<dyn SystemClock>,
         client: Arc<HttpClientWithUrl>,
         cx: &mut App,
+    ) -> Arc<Self> {
+        let telemetry = Self::new_internal(clock, client, no_telemetry_env_var_set(), cx);
+        if !telemetry.disabled {
+            let this = telemetry.clone();
+            cx.background_spawn(async move { this.replay_spool(Self::spool_file_path()) })
+                .detach_and_log_err(cx);
+        }
+        telemetry
+    }
+
+    fn new_internal(
+        clock: Arc<dyn SystemClock>,
+        client: Arc<HttpClientWithUrl>,
+        disabled: bool,
+        cx: &mut App,
     ) -> Arc<Self> {
         let release_channel =
             ReleaseChannel::try_global(cx).map(|release_channel| release_channel.display_name());
@@ -185,26 +488,109 @@ impl Telemetry {
             settings: TelemetrySettings {
                 diagnostics: false,
                 metrics: false,
+                flush_interval_secs: None,
+                max_queue_len: None,
+                max_batch_bytes: None,
+                telemetry_endpoint: None,
+                compress_telemetry: true,
+                crash_reports: false,
+                project_type_scan_cooldown_secs: None,
+                sample_rate: 1.0,
+                max_log_bytes: None,
+                dedup_window: None,
+                require_checksum: true,
+                user_agent: None,
+                persist_project_events: false,
+                include_session_id: true,
+                require_consent: false,
+                environment_tag: None,
+                server_accepts_gzip: None,
+                include_git_branch: false,
             },
             architecture: env::consts::ARCH,
             release_channel,
             system_id: None,
             installation_id: None,
+            anonymous: false,
             session_id: None,
             metrics_id: None,
             events_queue: Vec::new(),
             flush_events_task: None,
+            retry_flush_at: None,
             log_file: None,
             is_staff: None,
             first_event_date_time: None,
+            last_event_date_time: None,
             event_coalescer: EventCoalescer::new(clock.clone()),
+            last_edit_is_via_ssh: false,
+            last_edit_git_branch: None,
+            assistant_response_coalescer: AssistantResponseCoalescer::new(clock.clone()),
             max_queue_size: MAX_QUEUE_LEN,
-            worktrees_with_project_type_events_sent: HashSet::new(),
+            max_batch_bytes: MAX_BATCH_BYTES,
+            flush_interval: FLUSH_INTERVAL,
+            telemetry_endpoint: None,
+            allowed_hosts: Vec::new(),
+            compress_telemetry: true,
+            endpoint_accepts_gzip: None,
+            require_checksum: true,
+            user_agent: None,
+            events_recorded: 0,
+            events_flushed: 0,
+            events_dropped: 0,
+            worktrees_with_project_type_events_sent: HashMap::new(),
+            extension_loaded_events_sent: HashSet::new(),
+            worktree_opened_at: HashMap::new(),
+            worktrees_scanned_without_project_type: HashMap::new(),
+            project_type_scan_cooldown: PROJECT_TYPE_SCAN_COOLDOWN,
+            project_type_detectors: vec![
+                (DOTNET_PROJECT_FILES_REGEX.clone(), "dotnet"),
+                (TERRAFORM_PROJECT_FILES_REGEX.clone(), "terraform"),
+            ],
+            sampling_rng: StdRng::from_entropy(),
+            max_log_bytes: MAX_LOG_BYTES,
+            recent_events: RecentEventFilter::new(DEDUP_WINDOW),
+            reported_settings: reported_settings(cx.global::<SettingsStore>().raw_user_settings()),
+            worktrees_with_metrics_disabled: HashSet::new(),
+            project_event_store: Arc::new(KeyValueProjectEventStore),
+            project_opened_dates: None,
+            utc_anchor: (clock.utc_now(), chrono::Utc::now()),
+            pending_project_types: Vec::new(),
+            consent: None,
+            events_awaiting_consent: Vec::new(),
+            spool_path: None,
+            spool_buffer: Vec::new(),
+            spool_append_scheduled: false,
+            last_flushed_at: None,
+            flush_circuit: FlushCircuit::Closed {
+                consecutive_failures: 0,
+            },
+            #[cfg(any(test, feature = "test-support"))]
+            memory_sink: None,
 
             os_version: None,
+            os_version_probed_at: None,
             os_name: os_name(),
             app_version: release_channel::AppVersion::global(cx).to_string(),
         }));
+
+        if disabled {
+            return Arc::new(Self {
+                clock,
+                http_client: client,
+                executor: cx.background_executor().clone(),
+                state,
+                spool_lock: futures::lock::Mutex::new(()),
+                disabled,
+            });
+        }
+
+        {
+            let mut state = state.lock();
+            state.apply_settings(TelemetrySettings::get_global(cx));
+            state.allowed_hosts = ClientSettings::get_global(cx).allowed_hosts.clone();
+            state.consent = read_consent(state.project_event_store.as_ref());
+        }
+
         Self::log_file_path();
 
         let this = Arc::new(Self {
@@ -212,8 +598,40 @@ impl Telemetry {
             http_client: client,
             executor: cx.background_executor().clone(),
             state,
+            spool_lock: futures::lock::Mutex::new(()),
+            disabled,
         });
 
+        cx.observe_global::<SettingsStore>({
+            let this = Arc::downgrade(&this);
+            move |cx| {
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+                let reported_settings =
+                    reported_settings(cx.global::<SettingsStore>().raw_user_settings());
+                let changed_settings = {
+                    let mut state = this.state.lock();
+                    state.apply_settings(TelemetrySettings::get_global(cx));
+                    state.allowed_hosts = ClientSettings::get_global(cx).allowed_hosts.clone();
+                    state.worktrees_with_metrics_disabled =
+                        worktrees_with_metrics_disabled(cx.global::<SettingsStore>());
+                    let changed_settings =
+                        changed_settings(&state.reported_settings, &reported_settings);
+                    state.reported_settings = reported_settings;
+                    changed_settings
+                };
+
+                for key in changed_settings {
+                    this.report_event(Event::Flexible(FlexibleEvent {
+                        event_type: "Settings Changed".to_string(),
+                        event_properties: HashMap::from_iter([("key".to_string(), key.into())]),
+                    }));
+                }
+            }
+        })
+        .detach();
+
         let (tx, mut rx) = mpsc::unbounded();
         ::telemetry::init(tx);
 
@@ -222,7 +640,7 @@ impl Telemetry {
             async move {
                 while let Some(event) = rx.next().await {
                     let Some(state) = this.upgrade() else { break };
-                    state.report_event(Event::Flexible(event))
+                    state.report_event(Event::Flexible(event));
                 }
             }
         })
@@ -235,11 +653,13 @@ impl Telemetry {
             move |_| this.shutdown_telemetry()
         }));
 
+        this.load_project_opened_dates();
         this
     }
 
     #[cfg(any(test, feature = "test-support"))]
     fn shutdown_telemetry(self: &Arc<Self>) -> impl Future<Output = ()> + use<> {
+        self.close_periods();
         Task::ready(())
     }
 
@@ -247,19 +667,262 @@ impl Telemetry {
     // TestAppContext ends up calling this function on shutdown and it panics when trying to find the TelemetrySettings
     #[cfg(not(any(test, feature = "test-support")))]
     fn shutdown_telemetry(self: &Arc<Self>) -> impl Future<Output = ()> + use<> {
-        telemetry::event!("App Closed");
-        // TODO: close final edit period and make sure it's sent
-        Task::ready(())
+        self.close_periods();
+
+        self.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "App Closed".to_string(),
+            event_properties: HashMap::default(),
+        }));
+
+        use futures::FutureExt as _;
+
+        let flush = self.flush_events();
+        let timeout = self.executor.timer(SHUTDOWN_FLUSH_TIMEOUT);
+        async move {
+            futures::select_biased! {
+                result = flush.fuse() => {
+                    if let Some(event_count) = result.log_err() {
+                        log::info!("Flushed {event_count} telemetry events on shutdown");
+                    }
+                }
+                _ = timeout.fuse() => {
+                    log::warn!("Timed out flushing telemetry events on shutdown");
+                }
+            }
+        }
+    }
+
+    /// Reports the in-progress edit and assistant response periods, which would otherwise only be
+    /// reported once a later event closes them.
+    fn close_periods(self: &Arc<Self>) {
+        let (period_data, is_via_ssh, git_branch, assistant_response_period) = {
+            let mut state = self.state.lock();
+            (
+                state.event_coalescer.close_current_period(),
+                state.last_edit_is_via_ssh,
+                state.last_edit_git_branch.take(),
+                state.assistant_response_coalescer.flush(),
+            )
+        };
+        if let Some((start, end, environment)) = period_data {
+            self.report_edit_period(start, end, environment, is_via_ssh, git_branch);
+        }
+        if let Some(period) = assistant_response_period {
+            self.report_assistant_response_period(period);
+        }
+    }
+
+    /// Starts recording every reported event, before any sampling or gating, so that tests can
+    /// inspect them with [`Telemetry::recorded_events`].
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn with_memory_sink(self: &Arc<Self>) -> Arc<Self> {
+        if self.disabled {
+            return self.clone();
+        }
+        self.state.lock().memory_sink.get_or_insert_default();
+        self.clone()
+    }
+
+    /// Seeds the RNG that decides which events are sampled, so that tests are deterministic.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn with_sampling_seed(self: &Arc<Self>, seed: u64) -> Arc<Self> {
+        self.state.lock().sampling_rng = StdRng::seed_from_u64(seed);
+        self.clone()
+    }
+
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn with_project_event_store(
+        self: &Arc<Self>,
+        store: Arc<dyn ProjectEventStore>,
+    ) -> Arc<Self> {
+        let mut state = self.state.lock();
+        state.consent = read_consent(store.as_ref());
+        state.project_event_store = store;
+        drop(state);
+        self.load_project_opened_dates();
+        self.clone()
+    }
+
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn recorded_events(self: &Arc<Self>) -> Vec<Event> {
+        self.state.lock().memory_sink.clone().unwrap_or_default()
+    }
+
+    /// Whether events are being held back until the user decides whether to allow telemetry,
+    /// which is only asked for when the `require_consent` setting is on.
+    pub fn needs_consent(&self) -> bool {
+        let state = self.state.lock();
+        !self.disabled && state.settings.require_consent && state.consent.is_none()
+    }
+
+    /// Stores the user's consent decision. Events reported while it was pending are queued if
+    /// consent was granted, and dropped otherwise.
+    pub fn record_consent(self: &Arc<Self>, granted: bool) {
+        if self.disabled {
+            return;
+        }
+
+        let mut state = self.state.lock();
+        state.consent = Some(granted);
+        let write = state.project_event_store.write(
+            CONSENT_KEY.to_string(),
+            if granted { "granted" } else { "denied" }.to_string(),
+        );
+        self.executor
+            .spawn(async move { write.await.log_err() })
+            .detach();
+
+        let events = mem::take(&mut state.events_awaiting_consent);
+        if !granted {
+            state.events_dropped += events.len();
+            return;
+        }
+        drop(state);
+        for (event, worktree_id) in events {
+            self.queue_event(self.state.lock(), event, worktree_id);
+        }
     }
 
     pub fn log_file_path() -> PathBuf {
         paths::logs_dir().join("telemetry.log")
     }
 
+    fn spool_file_path() -> PathBuf {
+        paths::data_dir().join("telemetry_spool.jsonl")
+    }
+
+    /// Queues the events that a previous session spooled to `path` but didn't get to send, and
+    /// spools queued events there from now on. Returns how many events were replayed.
+    fn replay_spool(self: &Arc<Self>, path: PathBuf) -> Result<usize> {
+        let mut state = self.state.lock();
+        let mut events_queue = read_spool(&path)?;
+        let replayed = events_queue.len();
+        events_queue.append(&mut state.events_queue);
+        state.events_queue = events_queue;
+        write_spool(&path, &state.events_queue)?;
+        state.spool_path = Some(path);
+
+        if replayed > 0 && state.flush_events_task.is_none() {
+            let flush_interval = state.flush_interval;
+            self.schedule_flush(&mut state, flush_interval);
+        }
+        Ok(replayed)
+    }
+
+    /// Appends the buffered spool lines in the background, batching those of events queued
+    /// before it runs.
+    fn schedule_spool_append(self: &Arc<Self>, state: &mut TelemetryState) {
+        if state.spool_append_scheduled {
+            return;
+        }
+        state.spool_append_scheduled = true;
+
+        let this = self.clone();
+        self.executor
+            .spawn(async move {
+                let _spool_guard = this.spool_lock.lock().await;
+                let (spool_path, lines) = {
+                    let mut state = this.state.lock();
+                    state.spool_append_scheduled = false;
+                    (state.spool_path.clone(), mem::take(&mut state.spool_buffer))
+                };
+                if let Some(spool_path) = spool_path
+                    && !lines.is_empty()
+                    && let Err(error) = append_to_spool(&spool_path, &lines)
+                {
+                    log::error!("failed to spool telemetry events: {error:#}");
+                }
+            })
+            .detach();
+    }
+
+    /// Leaves only the events that are still queued in the spool, once the others were sent.
+    async fn rewrite_spool(self: &Arc<Self>) {
+        let _spool_guard = self.spool_lock.lock().await;
+        let (spool_path, contents) = {
+            let mut state = self.state.lock();
+            // The queue includes the buffered events, which would otherwise be spooled twice.
+            state.spool_buffer.clear();
+            (
+                state.spool_path.clone(),
+                spool_contents(&state.events_queue),
+            )
+        };
+        if let Some(spool_path) = spool_path
+            && let Err(error) = contents.and_then(|contents| {
+                std::fs::write(&spool_path, contents).context("failed to write the spool")
+            })
+        {
+            log::error!("failed to rewrite the telemetry spool: {error:#}");
+        }
+    }
+
+    /// Empties the telemetry log and deletes its rotated backups, discarding any queued events
+    /// and resetting the counters reported by [`Self::stats`].
+    pub fn clear_log(self: &Arc<Self>) -> Task<Result<()>> {
+        // Files left behind by an earlier session are removed even when telemetry is now disabled.
+        if !self.disabled {
+            let mut state = self.state.lock();
+            state.events_queue.clear();
+            state.flush_events_task.take();
+            state.events_recorded = 0;
+            state.events_flushed = 0;
+            state.events_dropped = 0;
+        }
+
+        let this = self.clone();
+        self.executor.spawn(async move {
+            let _spool_guard = this.spool_lock.lock().await;
+            let mut state = this.state.lock();
+            state.spool_buffer.clear();
+            let spool_path = state
+                .spool_path
+                .clone()
+                .unwrap_or_else(Self::spool_file_path);
+            remove_spool(&spool_path)?;
+            clear_log_files(
+                state.log_file.as_mut(),
+                &Self::log_file_path(),
+                MAX_LOG_BACKUPS,
+            )
+        })
+    }
+
     pub fn has_checksum_seed(&self) -> bool {
         ZED_CLIENT_CHECKSUM_SEED.is_some()
     }
 
+    pub fn clock(&self) -> Arc<dyn SystemClock> {
+        self.clock.clone()
+    }
+
+    /// Verifies that events will be signed correctly with the configured `ZED_CLIENT_CHECKSUM_SEED`.
+    ///
+    /// Succeeds trivially when no seed is configured, as events are then sent unsigned.
+    pub fn checksum_self_test(self: &Arc<Self>) -> Result<()> {
+        if self.disabled {
+            return Ok(());
+        }
+        let Some(checksum_seed) = &*ZED_CLIENT_CHECKSUM_SEED else {
+            return Ok(());
+        };
+
+        let checksum =
+            calculate_checksum_with_seed(CHECKSUM_SELF_TEST_SEED, &CHECKSUM_SELF_TEST_INPUT);
+        anyhow::ensure!(
+            checksum == CHECKSUM_SELF_TEST_EXPECTED,
+            "telemetry checksum self-test failed: expected {CHECKSUM_SELF_TEST_EXPECTED}, got {checksum}"
+        );
+
+        let checksum = calculate_checksum_with_seed(checksum_seed, &CHECKSUM_SELF_TEST_INPUT);
+        anyhow::ensure!(
+            is_well_formed_checksum(&checksum),
+            "telemetry checksum for the configured seed is malformed: {checksum}"
+        );
+
+        Ok(())
+    }
+
     pub fn start(
         self: &Arc<Self>,
         system_id: Option<String>,
@@ -267,12 +930,176 @@ impl Telemetry {
         session_id: String,
         cx: &App,
     ) {
+        if self.disabled {
+            return;
+        }
+
         let mut state = self.state.lock();
         state.system_id = system_id.map(|id| id.into());
         state.installation_id = installation_id.map(|id| id.into());
         state.session_id = Some(session_id);
         state.app_version = release_channel::AppVersion::global(cx).to_string();
         state.os_name = os_name();
+        drop(state);
+
+        let report_first_run = self.report_first_run();
+        cx.background_spawn(async move { report_first_run.await.log_err() })
+            .detach();
+
+        let this = self.clone();
+        cx.background_spawn(async move {
+            let log_file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(Self::log_file_path())
+                .log_err();
+            this.state.lock().log_file = log_file;
+        })
+        .detach();
+    }
+
+    /// Allows events to be sent without an installation ID, for builds that never call
+    /// [`Telemetry::start`], e.g. ones sending to their own collector. Otherwise flushing before
+    /// `start` fails, and the events stay queued.
+    pub fn set_anonymous(self: &Arc<Self>, anonymous: bool) {
+        self.state.lock().anonymous = anonymous;
+    }
+
+    /// Returns this installation's anonymous ID, generating and persisting a random one the first
+    /// time. The ID is only sent along with events, which are only sent when metrics are enabled.
+    ///
+    /// Returns an empty ID, without persisting anything, when telemetry is disabled via
+    /// [`NO_TELEMETRY_ENV_VAR`].
+    pub fn ensure_installation_id(self: &Arc<Self>) -> Task<Arc<str>> {
+        if self.disabled {
+            return Task::ready(Arc::from(""));
+        }
+        if let Some(installation_id) = self.state.lock().installation_id.clone() {
+            return Task::ready(installation_id);
+        }
+
+        let this = self.clone();
+        self.executor.spawn(async move {
+            let persisted = KEY_VALUE_STORE
+                .read_kvp(INSTALLATION_ID_KEY)
+                .log_err()
+                .flatten();
+            let is_new = persisted.is_none();
+            let installation_id: Arc<str> = persisted
+                .unwrap_or_else(|| Uuid::new_v4().to_string())
+                .into();
+
+            // Another call may have finished while this one was reading, in which case its ID
+            // is the one that's kept.
+            let (installation_id, is_new) = {
+                let mut state = this.state.lock();
+                match &state.installation_id {
+                    Some(existing) => (existing.clone(), false),
+                    None => {
+                        state.installation_id = Some(installation_id.clone());
+                        (installation_id, is_new)
+                    }
+                }
+            };
+            if is_new {
+                KEY_VALUE_STORE
+                    .write_kvp(INSTALLATION_ID_KEY.to_string(), installation_id.to_string())
+                    .await
+                    .log_err();
+            }
+            installation_id
+        })
+    }
+
+    /// Reports a "First Run" event, with how Fred was installed, the first time telemetry is
+    /// started on this machine. It's subject to the same gating as every other event, and is
+    /// attempted again on the next start until it's been queued.
+    fn report_first_run(self: &Arc<Self>) -> Task<Result<()>> {
+        if self.disabled {
+            return Task::ready(Ok(()));
+        }
+
+        let this = self.clone();
+        self.executor.spawn(async move {
+            let store = this.state.lock().project_event_store.clone();
+            if store.read(FIRST_RUN_KEY).is_some() {
+                return Ok(());
+            }
+
+            let source = install_source(
+                env::var(INSTALL_SOURCE_ENV_VAR).ok(),
+                &env::current_exe().unwrap_or_default(),
+            );
+            this.refresh_os_version();
+            let (os_name, os_version) = {
+                let state = this.state.lock();
+                (state.os_name.clone(), state.os_version.clone())
+            };
+            let queued = this.report_event(Event::Flexible(FlexibleEvent {
+                event_type: "First Run".to_string(),
+                event_properties: HashMap::from_iter([
+                    ("source".to_string(), source.into()),
+                    ("os_name".to_string(), os_name.into()),
+                    ("os_version".to_string(), os_version.into()),
+                ]),
+            }));
+            if queued {
+                store
+                    .write(FIRST_RUN_KEY.to_string(), "true".to_string())
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Records that an update failed, categorized (e.g. `"network"` or `"checksum"`) so that
+    /// failure modes can be aggregated. Subject to the same gating as every other event.
+    pub fn report_update_failure(self: &Arc<Self>, category: &str, message: &str) {
+        if self.disabled {
+            return;
+        }
+
+        self.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Update Failed".to_string(),
+            event_properties: HashMap::from_iter([
+                ("category".to_string(), category.into()),
+                ("message".to_string(), message.into()),
+            ]),
+        }));
+    }
+
+    /// Records that an extension was loaded. Each extension is only reported once per session,
+    /// so reloading it doesn't produce further events.
+    pub fn report_extension_loaded(self: &Arc<Self>, name: &str, version: &str) {
+        if self.disabled {
+            return;
+        }
+
+        if !self
+            .state
+            .lock()
+            .extension_loaded_events_sent
+            .insert(name.to_string())
+        {
+            return;
+        }
+
+        self.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Extension Loaded".to_string(),
+            event_properties: HashMap::from_iter([
+                ("extension_id".to_string(), name.into()),
+                ("version".to_string(), version.into()),
+            ]),
+        }));
+    }
+
+    /// The `User-Agent` to send with telemetry and update requests, e.g.
+    /// `Fred/1.2.3 (macOS; aarch64)`, unless overridden by the `user_agent` setting.
+    pub fn user_agent(self: &Arc<Self>) -> String {
+        let state = self.state.lock();
+        state.user_agent.clone().unwrap_or_else(|| {
+            format_user_agent(&state.app_version, &state.os_name, state.architecture)
+        })
     }
 
     pub fn metrics_enabled(self: &Arc<Self>) -> bool {
@@ -285,6 +1112,10 @@ impl Telemetry {
         metrics_id: Option<String>,
         is_staff: bool,
     ) {
+        if self.disabled {
+            return;
+        }
+
         let mut state = self.state.lock();
 
         if !state.settings.metrics {
@@ -298,6 +1129,30 @@ impl Telemetry {
     }
 
     pub fn report_assistant_event(self: &Arc<Self>, event: AssistantEventData) {
+        if self.disabled {
+            return;
+        }
+
+        // Failed responses are reported individually so their error messages aren't lost. There's
+        // no timer for closing a period of successful ones: it's reported when a later response or
+        // assistant event closes it, or on quit.
+        if event.phase == AssistantPhase::Response && event.error_message.is_none() {
+            let period = self
+                .state
+                .lock()
+                .assistant_response_coalescer
+                .log_response(event);
+            if let Some(period) = period {
+                self.report_assistant_response_period(period);
+            }
+            return;
+        }
+
+        let period = self.state.lock().assistant_response_coalescer.flush();
+        if let Some(period) = period {
+            self.report_assistant_response_period(period);
+        }
+
         let event_type = match event.phase {
             AssistantPhase::Response => "Assistant Responded",
             AssistantPhase::Invoked => "Assistant Invoked",
@@ -305,70 +1160,322 @@ impl Telemetry {
             AssistantPhase::Rejected => "Assistant Response Rejected",
         };
 
-        telemetry::event!(
-            event_type,
-            conversation_id = event.conversation_id,
-            kind = event.kind,
-            phase = event.phase,
-            message_id = event.message_id,
-            model = event.model,
-            model_provider = event.model_provider,
-            response_latency = event.response_latency,
-            error_message = event.error_message,
-            language_name = event.language_name,
+        telemetry::send_event(telemetry::Event {
+            event_type: event_type.to_string(),
+            event_properties: assistant_event_properties(&event),
+        });
+    }
+
+    fn report_assistant_response_period(self: &Arc<Self>, period: AssistantResponsePeriod) {
+        let mut event_properties = assistant_event_properties(&period.event);
+        // These describe a single response, so they don't apply to the period as a whole.
+        for key in ["message_id", "response_latency", "error_message"] {
+            event_properties.remove(key);
+        }
+        event_properties.insert("response_count".to_string(), period.count.into());
+        event_properties.insert(
+            "total_response_latency".to_string(),
+            serde_json::to_value(period.total_latency).unwrap_or_default(),
         );
+
+        self.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Assistant Responded".to_string(),
+            event_properties,
+        }));
     }
 
-    pub fn log_edit_event(self: &Arc<Self>, environment: &'static str, is_via_ssh: bool) {
+    /// Callers should only look up `git_branch` when [`Self::include_git_branch`] is true.
+    pub fn log_edit_event(
+        self: &Arc<Self>,
+        environment: &'static str,
+        is_via_ssh: bool,
+        git_branch: Option<String>,
+    ) {
+        if self.disabled {
+            return;
+        }
+
         let mut state = self.state.lock();
         let period_data = state.event_coalescer.log_event(environment);
+        state.last_edit_is_via_ssh = is_via_ssh;
+        let git_branch = git_branch.filter(|_| state.settings.include_git_branch);
+        // A closed period consists of the edits before this one, so it takes their branch.
+        let period_git_branch = mem::replace(&mut state.last_edit_git_branch, git_branch);
         drop(state);
 
         if let Some((start, end, environment)) = period_data {
-            let duration = end
-                .saturating_duration_since(start)
-                .min(Duration::from_secs(60 * 60 * 24))
-                .as_millis() as i64;
+            self.report_edit_period(start, end, environment, is_via_ssh, period_git_branch);
+        }
+    }
 
-            telemetry::event!(
-                "Editor Edited",
-                duration = duration,
-                environment = environment,
-                is_via_ssh = is_via_ssh
-            );
+    fn report_edit_period(
+        self: &Arc<Self>,
+        start: Instant,
+        end: Instant,
+        environment: &'static str,
+        is_via_ssh: bool,
+        git_branch: Option<String>,
+    ) {
+        let duration = clamped_millis_between(start, end);
+
+        let mut event_properties = HashMap::from_iter([
+            ("duration".to_string(), duration.into()),
+            ("environment".to_string(), environment.into()),
+            ("is_via_ssh".to_string(), is_via_ssh.into()),
+        ]);
+        if let Some(git_branch) = git_branch {
+            event_properties.insert("git_branch".to_string(), git_branch.into());
         }
+        self.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Editor Edited".to_string(),
+            event_properties,
+        }));
     }
 
+    /// Callers should only look up `git_branch` when [`Self::include_git_branch`] is true.
     pub fn report_discovered_project_type_events(
         self: &Arc<Self>,
         worktree_id: WorktreeId,
+        worktree_abs_path: &Path,
+        git_branch: Option<&str>,
         updated_entries_set: &UpdatedEntriesSet,
     ) {
+        if self.disabled {
+            return;
+        }
+
         let Some(project_types) = self.detect_project_types(worktree_id, updated_entries_set)
         else {
             return;
         };
-
-        for project_type in project_types {
-            telemetry::event!("Project Opened", project_type = project_type);
-        }
+        self.report_project_types(worktree_id, worktree_abs_path, git_branch, project_types);
     }
 
-    fn detect_project_types(
+    fn report_project_types(
         self: &Arc<Self>,
         worktree_id: WorktreeId,
-        updated_entries_set: &UpdatedEntriesSet,
-    ) -> Option<Vec<String>> {
-        let mut state = self.state.lock();
+        worktree_abs_path: &Path,
+        git_branch: Option<&str>,
+        project_types: Vec<(&'static str, String)>,
+    ) {
+        {
+            let mut state = self.state.lock();
+            if state.settings.persist_project_events && state.project_opened_dates.is_none() {
+                state.pending_project_types.push(PendingProjectTypes {
+                    worktree_id,
+                    worktree_abs_path: worktree_abs_path.to_path_buf(),
+                    git_branch: git_branch.map(ToOwned::to_owned),
+                    project_types,
+                });
+                return;
+            }
+        }
+
+        let project_types = self.unreported_project_types(worktree_abs_path, project_types);
+        if project_types.is_empty() {
+            return;
+        }
+
+        let git_branch = git_branch.filter(|_| self.state.lock().settings.include_git_branch);
+        for (project_type, path) in project_types {
+            let mut event_properties = vec![
+                ("project_type".to_string(), project_type.into()),
+                ("path".to_string(), path.into()),
+            ];
+            if let Some(git_branch) = git_branch {
+                event_properties.push(("git_branch".to_string(), git_branch.into()));
+            }
+            let event = Event::Flexible(FlexibleEvent {
+                event_type: "Project Opened".to_string(),
+                event_properties: event_properties.into_iter().collect(),
+            });
+            self.report_event_for_worktree(event, Some(worktree_id));
+        }
+    }
+
+    /// Detects additional project types from file names matching `detectors`, consulted after
+    /// the built-in ones.
+    pub fn register_project_type_detectors(
+        self: &Arc<Self>,
+        detectors: Vec<(Regex, &'static str)>,
+    ) {
+        self.state.lock().project_type_detectors.extend(detectors);
+    }
+
+    pub fn report_project_closed(self: &Arc<Self>, worktree_id: WorktreeId) {
+        if self.disabled {
+            return;
+        }
 
-        if state
+        let mut state = self.state.lock();
+        state
             .worktrees_with_project_type_events_sent
-            .contains(&worktree_id)
+            .remove(&worktree_id);
+        state
+            .worktrees_scanned_without_project_type
+            .remove(&worktree_id);
+        let opened_at = state.worktree_opened_at.remove(&worktree_id);
+        drop(state);
+
+        let Some(opened_at) = opened_at else {
+            return;
+        };
+
+        let duration = self
+            .clock
+            .utc_now()
+            .saturating_duration_since(opened_at)
+            .as_millis() as i64;
+
+        self.report_worktree_event(
+            worktree_id,
+            "Project Closed",
+            [("duration", duration.into())],
+        );
+    }
+
+    /// Whether events should carry the git branch they relate to. This is false whenever the
+    /// events couldn't be sent, so that callers can skip looking the branch up.
+    pub fn include_git_branch(self: &Arc<Self>) -> bool {
+        let state = self.state.lock();
+        !self.disabled && state.settings.metrics && state.settings.include_git_branch
+    }
+
+    /// When `persist_project_events` is enabled, drops the project types that were already
+    /// reported today for this path, and records the rest so that restarts skip them too.
+    fn unreported_project_types(
+        self: &Arc<Self>,
+        worktree_abs_path: &Path,
+        project_types: Vec<(&'static str, String)>,
+    ) -> Vec<(&'static str, String)> {
+        let mut state = self.state.lock();
+        if !state.settings.persist_project_events {
+            return project_types;
+        }
+        let store = state.project_event_store.clone();
+        let installation_id = state.installation_id.clone();
+        let Some(project_opened_dates) = state.project_opened_dates.as_mut() else {
+            return project_types;
+        };
+
+        let today = project_opened_day(state.utc_anchor, self.clock.utc_now());
+        project_types
+            .into_iter()
+            .filter(|(project_type, project_dir)| {
+                let key = project_opened_key(
+                    installation_id.as_deref().unwrap_or_default(),
+                    worktree_abs_path,
+                    project_type,
+                    project_dir,
+                );
+                if project_opened_dates.get(&key) == Some(&today) {
+                    return false;
+                }
+                project_opened_dates.insert(key.clone(), today.clone());
+                let write = store.write(key, today.clone());
+                self.executor
+                    .spawn(async move { write.await.log_err() })
+                    .detach();
+                true
+            })
+            .collect()
+    }
+
+    /// Loads the persisted "Project Opened" dates off the main thread, deleting those from
+    /// earlier days, then reports the project types that were discovered in the meantime.
+    fn load_project_opened_dates(self: &Arc<Self>) {
+        if self.disabled {
+            return;
+        }
+
+        let (store, today) = {
+            let mut state = self.state.lock();
+            state.project_opened_dates = None;
+            (
+                state.project_event_store.clone(),
+                project_opened_day(state.utc_anchor, self.clock.utc_now()),
+            )
+        };
+        let this = Arc::downgrade(self);
+        self.executor
+            .spawn(async move {
+                let entries = store
+                    .scan(PROJECT_OPENED_KEY_PREFIX)
+                    .await
+                    .log_err()
+                    .unwrap_or_default();
+                let mut project_opened_dates = HashMap::default();
+                for (key, day) in entries {
+                    if day == today {
+                        project_opened_dates.insert(key, day);
+                    } else {
+                        store.delete(key).await.log_err();
+                    }
+                }
+
+                let Some(this) = this.upgrade() else { return };
+                let pending_project_types = {
+                    let mut state = this.state.lock();
+                    // A store swapped in while loading has its own load under way.
+                    if !Arc::ptr_eq(&state.project_event_store, &store) {
+                        return;
+                    }
+                    state.project_opened_dates = Some(project_opened_dates);
+                    mem::take(&mut state.pending_project_types)
+                };
+                for pending in pending_project_types {
+                    this.report_project_types(
+                        pending.worktree_id,
+                        &pending.worktree_abs_path,
+                        pending.git_branch.as_deref(),
+                        pending.project_types,
+                    );
+                }
+            })
+            .detach();
+    }
+
+    /// Reports an event about a single worktree, so that the worktree's project settings can
+    /// disable it.
+    fn report_worktree_event<const N: usize>(
+        self: &Arc<Self>,
+        worktree_id: WorktreeId,
+        event_type: &str,
+        event_properties: [(&str, serde_json::Value); N],
+    ) {
+        let event = Event::Flexible(FlexibleEvent {
+            event_type: event_type.to_string(),
+            event_properties: event_properties
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        });
+        self.report_event_for_worktree(event, Some(worktree_id));
+    }
+
+    fn detect_project_types(
+        self: &Arc<Self>,
+        worktree_id: WorktreeId,
+        updated_entries_set: &UpdatedEntriesSet,
+    ) -> Option<Vec<(&'static str, String)>> {
+        let mut state = self.state.lock();
+
+        let now = self.clock.utc_now();
+        if let Some(scanned_at) = state
+            .worktrees_scanned_without_project_type
+            .get(&worktree_id)
         {
-            return None;
+            let cooling_down =
+                now.saturating_duration_since(*scanned_at) < state.project_type_scan_cooldown;
+            if cooling_down && !has_new_top_level_entries(updated_entries_set) {
+                return None;
+            }
         }
 
-        let mut project_types: HashSet<&str> = HashSet::new();
+        let already_sent = state
+            .worktrees_with_project_type_events_sent
+            .get(&worktree_id);
+        let mut project_types: HashSet<(&'static str, String)> = HashSet::new();
 
         for (path, _, _) in updated_entries_set.iter() {
             let Some(file_name) = path.file_name().and_then(|f| f.to_str()) else {
@@ -381,31 +1488,277 @@ impl Telemetry {
                 Some("yarn")
             } else if file_name == "package.json" {
                 Some("node")
-            } else if DOTNET_PROJECT_FILES_REGEX.is_match(file_name) {
-                Some("dotnet")
+            } else if file_name == "Gemfile" {
+                Some("ruby")
+            } else if file_name == "composer.json" {
+                Some("php")
+            } else if file_name == "mix.exs" {
+                Some("elixir")
+            } else if file_name == "Cargo.toml" {
+                Some("rust")
+            } else if matches!(
+                file_name,
+                "Dockerfile" | "docker-compose.yml" | "docker-compose.yaml"
+            ) {
+                Some("docker")
             } else {
-                None
+                state
+                    .project_type_detectors
+                    .iter()
+                    .find(|(regex, _)| regex.is_match(file_name))
+                    .map(|(_, project_type)| *project_type)
             };
 
             if let Some(project_type) = project_type {
-                project_types.insert(project_type);
+                let project_dir = project_dir(path);
+                let key = (project_type, project_dir);
+                if already_sent.is_none_or(|already_sent| !already_sent.contains(&key)) {
+                    project_types.insert(key);
+                }
             };
         }
 
-        if !project_types.is_empty() {
+        if project_types.is_empty() {
+            state
+                .worktrees_scanned_without_project_type
+                .insert(worktree_id, now);
+        } else {
             state
                 .worktrees_with_project_type_events_sent
-                .insert(worktree_id);
+                .entry(worktree_id)
+                .or_default()
+                .extend(project_types.iter().cloned());
+            state
+                .worktrees_scanned_without_project_type
+                .remove(&worktree_id);
+            state.worktree_opened_at.entry(worktree_id).or_insert(now);
         }
 
-        let mut project_types: Vec<_> = project_types.into_iter().map(String::from).collect();
+        let mut project_types: Vec<_> = project_types.into_iter().collect();
         project_types.sort();
         Some(project_types)
     }
 
-    fn report_event(self: &Arc<Self>, event: Event) {
-        // Fred does not do telemetry
-        return;
+    fn report_event(self: &Arc<Self>, event: Event) -> bool {
+        self.report_event_for_worktree(event, None)
+    }
+
+    /// Metrics can be turned off per project, which takes precedence over the user's setting,
+    /// which in turn takes precedence over the default. Events without a worktree only
+    /// consult the global setting.
+    ///
+    /// Returns whether the event was queued to be sent, as opposed to being dropped or held
+    /// until the user decides whether to allow telemetry.
+    fn report_event_for_worktree(
+        self: &Arc<Self>,
+        event: Event,
+        worktree_id: Option<WorktreeId>,
+    ) -> bool {
+        if self.disabled {
+            return false;
+        }
+
+        let mut state = self.state.lock();
+        state.events_recorded += 1;
+
+        #[cfg(any(test, feature = "test-support"))]
+        if let Some(memory_sink) = &mut state.memory_sink {
+            memory_sink.push(event.clone());
+        }
+
+        if state.settings.require_consent {
+            match state.consent {
+                Some(true) => {}
+                Some(false) => {
+                    state.events_dropped += 1;
+                    return false;
+                }
+                None => {
+                    state.events_awaiting_consent.push((event, worktree_id));
+                    let excess = state
+                        .events_awaiting_consent
+                        .len()
+                        .saturating_sub(state.max_queue_size);
+                    state.events_awaiting_consent.drain(..excess);
+                    state.events_dropped += excess;
+                    return false;
+                }
+            }
+        }
+
+        self.queue_event(state, event, worktree_id)
+    }
+
+    fn queue_event(
+        self: &Arc<Self>,
+        mut state: MutexGuard<TelemetryState>,
+        event: Event,
+        worktree_id: Option<WorktreeId>,
+    ) -> bool {
+        let sample_rate = state.settings.sample_rate;
+        if !should_sample_event(&event, sample_rate, &mut state.sampling_rng) {
+            state.events_dropped += 1;
+            return false;
+        }
+
+        if state.recent_events.is_duplicate(&event) {
+            state.events_dropped += 1;
+            return false;
+        }
+
+        // Fred pins metrics off, so events are only ever queued by builds that opt back in.
+        let metrics_disabled_for_worktree = worktree_id.is_some_and(|worktree_id| {
+            state.worktrees_with_metrics_disabled.contains(&worktree_id)
+        });
+        if !state.settings.metrics || metrics_disabled_for_worktree {
+            state.events_dropped += 1;
+            return false;
+        }
+
+        if state.flush_events_task.is_none() {
+            let flush_interval = state.flush_interval;
+            self.schedule_flush(&mut state, flush_interval);
+        }
+
+        let (date_time, clock_moved_backward) = self.next_event_date_time(&mut state);
+        let milliseconds_since_first_event = match state.first_event_date_time {
+            Some(first_event_date_time) => clamped_millis_between(first_event_date_time, date_time),
+            None => {
+                state.first_event_date_time = Some(date_time);
+                0
+            }
+        };
+
+        let signed_in = state.metrics_id.is_some();
+        let environment_tag = state.settings.environment_tag.clone();
+        let event = EventWrapper {
+            signed_in,
+            milliseconds_since_first_event,
+            clock_moved_backward,
+            environment_tag,
+            event,
+        };
+        if state.spool_path.is_some() {
+            let mut line = Vec::new();
+            match serde_json::to_writer(&mut line, &event) {
+                Ok(()) => {
+                    state.spool_buffer.extend_from_slice(&line);
+                    state.spool_buffer.push(b'\n');
+                    self.schedule_spool_append(&mut state);
+                }
+                Err(error) => log::error!("failed to spool telemetry event: {error:#}"),
+            }
+        }
+        state.events_queue.push(event);
+
+        let backing_off = state
+            .retry_flush_at
+            .is_some_and(|retry_flush_at| date_time < retry_flush_at);
+        if backing_off {
+            let excess = state
+                .events_queue
+                .len()
+                .saturating_sub(state.max_queue_size);
+            state.events_queue.drain(..excess);
+            state.events_dropped += excess;
+        } else if state.installation_id.is_some()
+            && state.events_queue.len() >= state.max_queue_size
+        {
+            drop(state);
+            self.flush_events().detach();
+        }
+        true
+    }
+
+    /// Returns the timestamp for a new event, and whether the clock has moved backward since the
+    /// previous one, e.g. after an NTP adjustment. Timestamps never go backward, so such an
+    /// event gets the previous event's timestamp instead.
+    fn next_event_date_time(self: &Arc<Self>, state: &mut TelemetryState) -> (Instant, bool) {
+        let now = self.clock.utc_now();
+        let (date_time, clock_moved_backward) = match state.last_event_date_time {
+            Some(last_event_date_time) if now < last_event_date_time => {
+                (last_event_date_time, true)
+            }
+            _ => (now, false),
+        };
+        state.last_event_date_time = Some(date_time);
+        (date_time, clock_moved_backward)
+    }
+
+    fn schedule_flush(self: &Arc<Self>, state: &mut TelemetryState, delay: Duration) {
+        let this = self.clone();
+        // Waiting on the telemetry clock lets tests drive flushes by advancing a fake clock.
+        let wait_for_flush = self.clock.wait_until(self.clock.utc_now() + delay);
+        state.flush_events_task = Some(self.executor.spawn(async move {
+            match wait_for_flush {
+                Some(wait_for_flush) => wait_for_flush.await,
+                None => this.executor.timer(delay).await,
+            }
+            this.flush_events().detach();
+        }));
+    }
+
+    /// Puts events the collector refused back at the front of the queue, and holds them until
+    /// `retry_after` has passed. The oldest events are dropped to keep the queue within
+    /// `max_queue_size`.
+    fn requeue_events(self: &Arc<Self>, events: Vec<EventWrapper>, retry_after: Duration) {
+        let mut state = self.state.lock();
+        let mut events_queue = events;
+        events_queue.append(&mut state.events_queue);
+        let excess = events_queue.len().saturating_sub(state.max_queue_size);
+        events_queue.drain(..excess);
+        state.events_dropped += excess;
+        state.events_queue = events_queue;
+        state.retry_flush_at = Some(self.clock.utc_now() + retry_after);
+        self.schedule_flush(&mut state, retry_after);
+    }
+
+    pub fn upload_minidump(self: &Arc<Self>, path: PathBuf) -> Task<Result<()>> {
+        if self.disabled || !self.state.lock().settings.crash_reports {
+            return Task::ready(Ok(()));
+        }
+
+        let Some(endpoint) = MINIDUMP_ENDPOINT.clone() else {
+            return Task::ready(Err(anyhow::anyhow!("Minidump endpoint not set")));
+        };
+
+        let http_client = self.http_client.clone();
+        self.executor
+            .spawn(async move { upload_minidump(&http_client, &endpoint, &path).await })
+    }
+
+    /// Uploads every `*.dmp` file in `dir`, deleting each once it's been uploaded so that a
+    /// crash handler can leave dumps there to be sent on the next launch. Dumps that fail to
+    /// upload are kept for a later attempt. Returns how many were uploaded.
+    pub fn scan_and_upload_minidumps(self: &Arc<Self>, dir: PathBuf) -> Task<Result<usize>> {
+        if self.disabled || !self.state.lock().settings.crash_reports {
+            return Task::ready(Ok(0));
+        }
+
+        let Some(endpoint) = MINIDUMP_ENDPOINT.clone() else {
+            return Task::ready(Err(anyhow::anyhow!("Minidump endpoint not set")));
+        };
+
+        let http_client = self.http_client.clone();
+        self.executor
+            .spawn(async move { upload_minidumps_in_dir(&http_client, &endpoint, &dir).await })
+    }
+
+    pub fn stats(self: &Arc<Self>) -> TelemetryStats {
+        self.state.lock().stats()
+    }
+
+    pub fn snapshot(self: &Arc<Self>) -> TelemetrySnapshot {
+        let state = self.state.lock();
+        TelemetrySnapshot {
+            settings: state.settings.clone(),
+            disabled: self.disabled,
+            has_checksum_seed: self.has_checksum_seed(),
+            consent: state.consent,
+            events_awaiting_consent: state.events_awaiting_consent.len(),
+            last_flushed_at: state.last_flushed_at,
+            stats: state.stats(),
+        }
     }
 
     pub fn metrics_id(self: &Arc<Self>) -> Option<Arc<str>> {
@@ -429,46 +1782,2616 @@ impl Telemetry {
         // We take in the JSON bytes buffer so we can reuse the existing allocation.
         mut json_bytes: Vec<u8>,
         event_request: &EventRequestBody,
+        compress: bool,
     ) -> Result<Request<AsyncBody>> {
         json_bytes.clear();
         serde_json::to_writer(&mut json_bytes, event_request)?;
 
-        let checksum = calculate_json_checksum(&json_bytes).unwrap_or_default();
+        let require_checksum = self.state.lock().require_checksum;
+        let (body, content_encoding) = encode_request_body(json_bytes, compress)?;
+        // The server verifies the checksum before decoding, so it covers the bytes on the wire.
+        let checksum =
+            calculate_json_checksum(&body).or_else(|| require_checksum.then(String::new));
 
-        Ok(Request::builder()
+        let mut request = Request::builder()
             .method(Method::POST)
-            .uri(
-                self.http_client
-                    .build_zed_api_url("/telemetry/events", &[])?
-                    .as_ref(),
-            )
+            .uri(self.endpoint_url()?)
             .header("Content-Type", "application/json")
-            .header("x-zed-checksum", checksum)
-            .body(json_bytes.into())?)
+            .header("User-Agent", self.user_agent());
+        if let Some(checksum) = checksum {
+            request = request.header("x-zed-checksum", checksum);
+        }
+        if let Some(content_encoding) = content_encoding {
+            request = request.header("Content-Encoding", content_encoding);
+        }
+
+        Ok(request.body(body.into())?)
+    }
+
+    /// Probes the OS version again once the cached one is older than `OS_VERSION_TTL`, so that
+    /// long-running instances notice an OS upgrade. This might do blocking IO, so it's only
+    /// called on the background executor.
+    fn refresh_os_version(self: &Arc<Self>) {
+        let now = self.clock.utc_now();
+        let is_stale = self
+            .state
+            .lock()
+            .os_version_probed_at
+            .is_none_or(|probed_at| now.saturating_duration_since(probed_at) >= OS_VERSION_TTL);
+        if !is_stale {
+            return;
+        }
+
+        let os_version = os_version();
+        let mut state = self.state.lock();
+        state.os_version = Some(os_version);
+        state.os_version_probed_at = Some(now);
+    }
+
+    /// Fails if the endpoint's host isn't allowed by the `allowed_hosts` setting.
+    fn endpoint_url(self: &Arc<Self>) -> Result<String> {
+        let (telemetry_endpoint, allowed_hosts) = {
+            let state = self.state.lock();
+            (
+                state.telemetry_endpoint.clone(),
+                state.allowed_hosts.clone(),
+            )
+        };
+        let url = match telemetry_endpoint {
+            Some(telemetry_endpoint) => telemetry_endpoint,
+            None => self
+                .http_client
+                .build_zed_api_url("/telemetry/events", &[])?
+                .to_string(),
+        };
+        ensure_host_allowed(&url, &allowed_hosts)?;
+        Ok(url)
+    }
+
+    /// Whether to gzip event batches. Fred's own API always accepts gzip, whereas a custom
+    /// endpoint is asked once, unless the `server_accepts_gzip` setting says whether it does.
+    async fn should_compress(self: &Arc<Self>) -> bool {
+        let (compress_telemetry, server_accepts_gzip, is_custom_endpoint, endpoint_accepts_gzip) = {
+            let state = self.state.lock();
+            (
+                state.compress_telemetry,
+                state.settings.server_accepts_gzip,
+                state.telemetry_endpoint.is_some(),
+                state.endpoint_accepts_gzip,
+            )
+        };
+        if !compress_telemetry {
+            return false;
+        }
+        if let Some(server_accepts_gzip) = server_accepts_gzip {
+            return server_accepts_gzip;
+        }
+        if !is_custom_endpoint {
+            return true;
+        }
+        if let Some(endpoint_accepts_gzip) = endpoint_accepts_gzip {
+            return endpoint_accepts_gzip;
+        }
+
+        let accepts_gzip = match self.probe_gzip_support().await {
+            Ok(accepts_gzip) => accepts_gzip,
+            Err(error) => {
+                log::warn!(
+                    "failed to ask the telemetry endpoint whether it accepts gzip: {error:#}"
+                );
+                false
+            }
+        };
+        self.state.lock().endpoint_accepts_gzip = Some(accepts_gzip);
+        accepts_gzip
+    }
+
+    /// Servers list the content codings they accept for requests in an `Accept-Encoding`
+    /// response header (RFC 7694).
+    async fn probe_gzip_support(self: &Arc<Self>) -> Result<bool> {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(self.endpoint_url()?)
+            .header("User-Agent", self.user_agent())
+            .body(AsyncBody::default())?;
+        let response = self.http_client.send(request).await?;
+        Ok(response
+            .headers()
+            .get_all("Accept-Encoding")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|coding| {
+                coding
+                    .split(';')
+                    .next()
+                    .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("gzip"))
+            }))
+    }
+
+    /// Checks that the configured endpoint is reachable, without sending any events, returning
+    /// the round-trip time. Collectors that only accept `POST` may reject the `HEAD` request
+    /// with a 405, which still counts as reachable.
+    pub fn ping_endpoint(self: &Arc<Self>) -> Task<Result<Duration>> {
+        if self.disabled {
+            return Task::ready(Err(anyhow::anyhow!("telemetry is disabled")));
+        }
+
+        let request = self.endpoint_url().and_then(|url| {
+            Ok(Request::builder()
+                .method(Method::HEAD)
+                .uri(url)
+                .header("User-Agent", self.user_agent())
+                .body(AsyncBody::default())?)
+        });
+        let http_client = self.http_client.clone();
+        self.executor.spawn(async move {
+            let started_at = Instant::now();
+            let response = http_client.send(request?).await?;
+            let round_trip_time = started_at.elapsed();
+            anyhow::ensure!(
+                response.status().is_success() || response.status() == 405,
+                "telemetry endpoint responded with HTTP {:?}",
+                response.status()
+            );
+            Ok(round_trip_time)
+        })
+    }
+
+    /// Sends all queued events, returning how many were sent. Events that the collector doesn't
+    /// accept stay queued, and spooled, for the next flush.
+    pub fn flush_events(self: &Arc<Self>) -> Task<Result<usize>> {
+        if self.disabled {
+            return Task::ready(Ok(0));
+        }
+
+        self.refresh_os_version();
+        let mut state = self.state.lock();
+        if !state.flush_circuit.allows_flush(self.clock.utc_now()) {
+            return Task::ready(Ok(0));
+        }
+
+        // Build the request before taking the queue, so that events stay queued if it can't be.
+        let request_body = match EventRequestBody::builder()
+            .system_id(state.system_id.as_deref().map(Into::into))
+            .installation_id(state.installation_id.as_deref().map(Into::into))
+            .anonymous(state.anonymous)
+            .session_id(
+                state
+                    .session_id
+                    .clone()
+                    .filter(|_| state.settings.include_session_id),
+            )
+            .metrics_id(state.metrics_id.as_deref().map(Into::into))
+            .is_staff(state.is_staff)
+            .app_version(state.app_version.clone())
+            .os_name(state.os_name.clone())
+            .os_version(state.os_version.clone())
+            .architecture(state.architecture)
+            .release_channel(state.release_channel.map(Into::into))
+            .environment_tag(state.settings.environment_tag.clone())
+            .build()
+        {
+            Ok(request_body) => request_body,
+            Err(error) => return Task::ready(Err(error.into())),
+        };
+        let max_batch_bytes = state.max_batch_bytes;
+        state.first_event_date_time = None;
+        let events = mem::take(&mut state.events_queue);
+        state.flush_events_task.take();
+        drop(state);
+        if events.is_empty() {
+            return Task::ready(Ok(0));
+        }
+
+        let this = self.clone();
+        let flush = async move {
+            let mut json_bytes = Vec::new();
+            let compress = this.should_compress().await;
+            let mut event_count = 0;
+            let mut batches = split_into_batches(events, max_batch_bytes)?.into_iter();
+            while let Some(events) = batches.next() {
+                let request_body = EventRequestBody {
+                    events,
+                    ..request_body.clone()
+                };
+                let response =
+                    match this.build_request(mem::take(&mut json_bytes), &request_body, compress) {
+                        Ok(request) => this.http_client.send(request).await,
+                        Err(error) => Err(error),
+                    }
+                    .and_then(|response| {
+                        anyhow::ensure!(
+                            response.status() == 200 || response.status() == 429,
+                            "Failed to send events: HTTP {:?}",
+                            response.status()
+                        );
+                        Ok(response)
+                    });
+                let response = match response {
+                    Ok(response) => response,
+                    Err(error) => {
+                        // Events only leave the queue, and so the spool, once the collector has
+                        // accepted them.
+                        let unsent_events = request_body
+                            .events
+                            .into_iter()
+                            .chain(batches.flatten())
+                            .collect();
+                        let flush_interval = this.state.lock().flush_interval;
+                        this.requeue_events(unsent_events, flush_interval);
+                        this.rewrite_spool().await;
+                        return Err(error);
+                    }
+                };
+                if response.status() == 429 {
+                    let retry_after = parse_retry_after(
+                        response
+                            .headers()
+                            .get("Retry-After")
+                            .and_then(|value| value.to_str().ok()),
+                    )
+                    .unwrap_or_else(|| this.state.lock().flush_interval);
+                    log::warn!("telemetry collector is rate limiting, retrying in {retry_after:?}");
+                    let unsent_events = request_body
+                        .events
+                        .into_iter()
+                        .chain(batches.flatten())
+                        .collect();
+                    this.requeue_events(unsent_events, retry_after);
+                    this.rewrite_spool().await;
+                    return Ok(event_count);
+                }
+                // Only accepted events are logged, so that requeued ones aren't logged again.
+                this.log_events(&request_body.events).log_err();
+                event_count += request_body.events.len();
+                this.state.lock().events_flushed += request_body.events.len();
+            }
+            this.state.lock().last_flushed_at = Some(this.clock.utc_now());
+            this.rewrite_spool().await;
+            anyhow::Ok(event_count)
+        };
+
+        let this = self.clone();
+        self.executor.spawn(async move {
+            let result = flush.await;
+            this.record_flush_outcome(result.is_ok());
+            result
+        })
     }
 
-    pub fn flush_events(self: &Arc<Self>) -> Task<()> {
-        // Fred does not do telemetry
+    fn log_events(self: &Arc<Self>, events: &[EventWrapper]) -> Result<()> {
         let mut state = self.state.lock();
-        state.events_queue.clear();
-        return Task::ready(());
+        let max_log_bytes = state.max_log_bytes;
+        let Some(file) = &mut state.log_file else {
+            return Ok(());
+        };
+        rotate_log_file_if_needed(file, &Self::log_file_path(), max_log_bytes, MAX_LOG_BACKUPS)?;
+        let mut json_bytes = Vec::new();
+        for event in events {
+            json_bytes.clear();
+            serde_json::to_writer(&mut json_bytes, event)?;
+            file.write_all(&json_bytes)?;
+            file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Opens the flush circuit after repeated failures, holding queued events until the
+    /// cooldown has passed and a flush can test whether the collector has recovered.
+    fn record_flush_outcome(self: &Arc<Self>, succeeded: bool) {
+        let mut state = self.state.lock();
+        let now = self.clock.utc_now();
+        let previous = state.flush_circuit;
+        state.flush_circuit.record_flush(succeeded, now);
+        match (previous, state.flush_circuit) {
+            (FlushCircuit::HalfOpen, FlushCircuit::Closed { .. }) => {
+                log::info!("telemetry collector recovered, resuming flushes");
+            }
+            (FlushCircuit::Closed { .. }, FlushCircuit::Open { .. }) => {
+                log::warn!(
+                    "{FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT} telemetry flushes failed in a row, \
+                     pausing them for {FLUSH_CIRCUIT_COOLDOWN:?}"
+                );
+            }
+            _ => {}
+        }
+        if let FlushCircuit::Open { until } = state.flush_circuit {
+            state.retry_flush_at = Some(until);
+            self.schedule_flush(&mut state, until.saturating_duration_since(now));
+        }
     }
 }
 
-pub fn calculate_json_checksum(json: &impl AsRef<[u8]>) -> Option<String> {
-    let Some(checksum_seed) = &*ZED_CLIENT_CHECKSUM_SEED else {
-        return None;
-    };
+/// Splits `events` into batches whose serialized events add up to at most `max_bytes`, so that
+/// a few huge events can't produce an oversized request. An event larger than `max_bytes` is
+/// sent in a batch of its own.
+fn split_into_batches(
+    events: Vec<EventWrapper>,
+    max_bytes: usize,
+) -> Result<Vec<Vec<EventWrapper>>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    let mut json_bytes = Vec::new();
+    for event in events {
+        json_bytes.clear();
+        serde_json::to_writer(&mut json_bytes, &event)?;
+        if !batch.is_empty() && batch_bytes + json_bytes.len() > max_bytes {
+            batches.push(mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch_bytes += json_bytes.len();
+        batch.push(event);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    Ok(batches)
+}
 
-    let mut summer = Sha256::new();
-    summer.update(checksum_seed);
-    summer.update(json);
-    summer.update(checksum_seed);
-    let mut checksum = String::new();
-    for byte in summer.finalize().as_slice() {
-        use std::fmt::Write;
-        write!(&mut checksum, "{:02x}", byte).unwrap();
+/// Only the delay-seconds form of `Retry-After` is supported, not HTTP dates.
+fn parse_retry_after(retry_after: Option<&str>) -> Option<Duration> {
+    let seconds = retry_after?.trim().parse::<u64>().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
+}
+
+/// An empty seed would produce unkeyed checksums, so it's treated as no seed at all.
+fn parse_checksum_seed(seed: &str) -> Option<Vec<u8>> {
+    (!seed.trim().is_empty()).then(|| seed.as_bytes().into())
+}
+
+fn worktrees_with_metrics_disabled(settings_store: &SettingsStore) -> HashSet<WorktreeId> {
+    settings_store
+        .get_all_locals::<TelemetrySettings>()
+        .into_iter()
+        .filter(|(_, path, settings)| path.as_os_str().is_empty() && !settings.metrics)
+        .map(|(worktree_id, _, _)| worktree_id)
+        .collect()
+}
+
+fn format_user_agent(app_version: &str, os_name: &str, architecture: &str) -> String {
+    format!("Fred/{app_version} ({os_name}; {architecture})")
+}
+
+fn no_telemetry_env_var_set() -> bool {
+    env::var_os(NO_TELEMETRY_ENV_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// The properties of an assistant event. Token usage and cost are left out, rather than
+/// reported as zero, when they weren't reported.
+fn assistant_event_properties(event: &AssistantEventData) -> HashMap<String, serde_json::Value> {
+    match serde_json::to_value(event) {
+        Ok(serde_json::Value::Object(properties)) => properties.into_iter().collect(),
+        _ => HashMap::new(),
+    }
+}
+
+async fn upload_minidump(
+    http_client: &HttpClientWithUrl,
+    endpoint: &str,
+    path: &Path,
+) -> Result<()> {
+    let minidump = smol::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read minidump {path:?}"))?;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(endpoint)
+        .header("Content-Type", "application/octet-stream")
+        .body(minidump.into())?;
+
+    let response = http_client.send(request).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "failed to upload minidump: HTTP {:?}",
+        response.status()
+    );
+    Ok(())
+}
+
+async fn upload_minidumps_in_dir(
+    http_client: &HttpClientWithUrl,
+    endpoint: &str,
+    dir: &Path,
+) -> Result<usize> {
+    let mut entries = smol::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("Failed to read minidump directory {dir:?}"))?;
+    let mut uploaded = 0;
+    while let Some(entry) = entries.next().await {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(error) => {
+                log::warn!("failed to read an entry of minidump directory {dir:?}: {error:?}");
+                continue;
+            }
+        };
+        if path.extension().is_none_or(|extension| extension != "dmp") {
+            continue;
+        }
+        match upload_minidump(http_client, endpoint, &path).await {
+            Ok(()) => {
+                uploaded += 1;
+                if let Err(error) = smol::fs::remove_file(&path).await {
+                    log::error!("failed to delete uploaded minidump {path:?}: {error:?}");
+                }
+            }
+            Err(error) => log::warn!("keeping minidump {path:?} to retry later: {error:?}"),
+        }
+    }
+    Ok(uploaded)
+}
+
+/// Moves `path` to `path.1` once `file` has grown past `max_bytes`, shifting older
+/// backups along and discarding any beyond `backups`, then reopens `path` empty.
+fn rotate_log_file_if_needed(
+    file: &mut File,
+    path: &Path,
+    max_bytes: u64,
+    backups: usize,
+) -> Result<()> {
+    if file.metadata()?.len() <= max_bytes {
+        return Ok(());
+    }
+
+    for index in (1..backups).rev() {
+        let from = log_backup_path(path, index);
+        if from.exists() {
+            std::fs::rename(&from, log_backup_path(path, index + 1))?;
+        }
     }
+    if backups > 0 {
+        std::fs::rename(path, log_backup_path(path, 1))?;
+    }
+
+    *file = File::create(path)?;
+    Ok(())
+}
+
+/// Truncates the log at `path` and deletes up to `backups` of its rotated backups. `file` is
+/// the handle events are currently appended through, if the log has been opened.
+fn clear_log_files(file: Option<&mut File>, path: &Path, backups: usize) -> Result<()> {
+    match file {
+        Some(file) => file.set_len(0)?,
+        None if path.exists() => {
+            File::create(path)?;
+        }
+        None => {}
+    }
+
+    for index in 1..=backups {
+        let backup = log_backup_path(path, index);
+        if backup.exists() {
+            std::fs::remove_file(backup)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_spool(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+fn append_to_spool(path: &Path, lines: &[u8]) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(lines)?;
+    Ok(())
+}
 
-    Some(checksum)
+fn spool_contents(events: &[EventWrapper]) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    for event in events {
+        serde_json::to_writer(&mut contents, event)?;
+        contents.push(b'\n');
+    }
+    Ok(contents)
+}
+
+fn write_spool(path: &Path, events: &[EventWrapper]) -> Result<()> {
+    std::fs::write(path, spool_contents(events)?)?;
+    Ok(())
+}
+
+/// A line that doesn't parse, e.g. because the app crashed while appending it, is skipped.
+fn read_spool(path: &Path) -> Result<Vec<EventWrapper>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(event) => Some(event),
+            Err(error) => {
+                log::warn!("skipping malformed spooled telemetry event: {error}");
+                None
+            }
+        })
+        .collect())
+}
+
+fn log_backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{index}"));
+    PathBuf::from(backup)
+}
+
+/// Prefers the source reported through [`INSTALL_SOURCE_ENV_VAR`], falling back to where
+/// package managers are known to put executables.
+fn install_source(reported_source: Option<String>, executable: &Path) -> String {
+    if let Some(source) = reported_source.filter(|source| !source.trim().is_empty()) {
+        return source.trim().to_string();
+    }
+
+    const PATH_MARKERS: &[(&str, &str)] = &[
+        ("/nix/store/", "nix"),
+        ("/opt/homebrew/", "homebrew"),
+        ("/usr/local/Cellar/", "homebrew"),
+        ("/home/linuxbrew/", "homebrew"),
+        ("/snap/", "snap"),
+        ("/flatpak/", "flatpak"),
+        ("/.cargo/bin/", "cargo"),
+        ("/usr/bin/", "system package"),
+        ("/usr/lib/", "system package"),
+    ];
+    let executable = executable.to_string_lossy();
+    PATH_MARKERS
+        .iter()
+        .find(|(marker, _)| executable.contains(marker))
+        .map_or("direct download", |(_, source)| source)
+        .to_string()
+}
+
+fn read_consent(store: &dyn ProjectEventStore) -> Option<bool> {
+    match store.read(CONSENT_KEY)?.as_str() {
+        "granted" => Some(true),
+        "denied" => Some(false),
+        _ => None,
+    }
+}
+
+fn clamped_millis_between(start: Instant, end: Instant) -> i64 {
+    end.saturating_duration_since(start)
+        .min(MAX_EVENT_DURATION)
+        .as_millis() as i64
+}
+
+fn should_sample_event(event: &Event, sample_rate: f32, rng: &mut impl Rng) -> bool {
+    let is_exempt = match event {
+        Event::Assistant(_) => true,
+        Event::Flexible(event) => {
+            event.event_type.starts_with("Assistant ") || event.event_type.starts_with("Project ")
+        }
+        _ => false,
+    };
+
+    is_exempt || rng.gen_bool(sample_rate.clamp(0.0, 1.0) as f64)
+}
+
+fn reported_settings(
+    raw_user_settings: &serde_json::Value,
+) -> HashMap<&'static str, serde_json::Value> {
+    REPORTED_SETTINGS
+        .iter()
+        .filter_map(|key| Some((*key, raw_user_settings.get(key)?.clone())))
+        .collect()
+}
+
+fn changed_settings(
+    old: &HashMap<&'static str, serde_json::Value>,
+    new: &HashMap<&'static str, serde_json::Value>,
+) -> Vec<&'static str> {
+    REPORTED_SETTINGS
+        .iter()
+        .copied()
+        .filter(|key| old.get(key) != new.get(key))
+        .collect()
+}
+
+/// Stops flushes to a collector that keeps failing, so that it isn't sent a doomed request
+/// every flush interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FlushCircuit {
+    Closed {
+        consecutive_failures: usize,
+    },
+    /// Flushes are skipped until `until`.
+    Open {
+        until: Instant,
+    },
+    /// The cooldown has passed, and the next flush tests whether the collector has recovered.
+    HalfOpen,
+}
+
+impl FlushCircuit {
+    fn allows_flush(&mut self, now: Instant) -> bool {
+        if let Self::Open { until } = *self {
+            if now < until {
+                return false;
+            }
+            *self = Self::HalfOpen;
+        }
+        true
+    }
+
+    fn record_flush(&mut self, succeeded: bool, now: Instant) {
+        *self = match *self {
+            _ if succeeded => Self::Closed {
+                consecutive_failures: 0,
+            },
+            Self::Closed {
+                consecutive_failures,
+            } if consecutive_failures + 1 < FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT => Self::Closed {
+                consecutive_failures: consecutive_failures + 1,
+            },
+            _ => Self::Open {
+                until: now + FLUSH_CIRCUIT_COOLDOWN,
+            },
+        };
+    }
+}
+
+/// Remembers the fingerprints of the last `window` events, so that repeats of them, such as
+/// those caused by worktree rescans, can be skipped.
+struct RecentEventFilter {
+    window: usize,
+    fingerprints: VecDeque<u64>,
+}
+
+impl RecentEventFilter {
+    fn new(window: usize) -> Self {
+        Self {
+            window,
+            fingerprints: VecDeque::new(),
+        }
+    }
+
+    fn is_duplicate(&mut self, event: &Event) -> bool {
+        let Some(fingerprint) = event_fingerprint(event) else {
+            return false;
+        };
+        if self.fingerprints.contains(&fingerprint) {
+            return true;
+        }
+
+        self.fingerprints.push_back(fingerprint);
+        while self.fingerprints.len() > self.window {
+            self.fingerprints.pop_front();
+        }
+        false
+    }
+}
+
+/// Hashes an event's type and properties, or returns `None` if the event is exempt from
+/// deduplication.
+fn event_fingerprint(event: &Event) -> Option<u64> {
+    let Event::Flexible(event) = event else {
+        return None;
+    };
+    if DEDUP_EXEMPT_EVENT_TYPES.contains(&event.event_type.as_str()) {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    event.event_type.hash(&mut hasher);
+    // Property order isn't stable, so sort before hashing.
+    let mut properties = event.event_properties.iter().collect::<Vec<_>>();
+    properties.sort_unstable_by_key(|(key, _)| *key);
+    for (key, value) in properties {
+        key.hash(&mut hasher);
+        value.to_string().hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+/// The directory containing a project marker file, relative to the worktree root.
+fn project_dir(marker_path: &Path) -> String {
+    match marker_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    }
+}
+
+fn has_new_top_level_entries(updated_entries_set: &UpdatedEntriesSet) -> bool {
+    updated_entries_set.iter().any(|(path, _, change)| {
+        path.components().count() == 1
+            && matches!(
+                change,
+                PathChange::Added | PathChange::AddedOrUpdated | PathChange::Loaded
+            )
+    })
+}
+
+pub fn calculate_json_checksum(json: &impl AsRef<[u8]>) -> Option<String> {
+    let Some(checksum_seed) = &*ZED_CLIENT_CHECKSUM_SEED else {
+        return None;
+    };
+
+    Some(calculate_checksum_with_seed(checksum_seed, json))
+}
+
+fn encode_request_body(
+    json_bytes: Vec<u8>,
+    compress: bool,
+) -> Result<(Vec<u8>, Option<&'static str>)> {
+    if !compress || json_bytes.len() < COMPRESSION_THRESHOLD_BYTES {
+        return Ok((json_bytes, None));
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json_bytes)?;
+    Ok((encoder.finish()?, Some("gzip")))
+}
+
+/// "Project Opened" events are deduplicated per UTC day.
+fn project_opened_day(
+    (anchor_instant, anchor_utc): (Instant, chrono::DateTime<chrono::Utc>),
+    now: Instant,
+) -> String {
+    let elapsed = chrono::Duration::from_std(now.saturating_duration_since(anchor_instant))
+        .unwrap_or_else(|_| chrono::Duration::zero());
+    (anchor_utc + elapsed).date_naive().to_string()
+}
+
+/// Hashes the path so that the store doesn't hold the paths of the user's projects.
+fn project_opened_key(
+    installation_id: &str,
+    worktree_abs_path: &Path,
+    project_type: &str,
+    project_dir: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(installation_id);
+    hasher.update(worktree_abs_path.to_string_lossy().as_bytes());
+    hasher.update(project_type);
+    hasher.update(project_dir);
+    let mut key = PROJECT_OPENED_KEY_PREFIX.to_string();
+    for byte in hasher.finalize().as_slice() {
+        use std::fmt::Write;
+        write!(&mut key, "{:02x}", byte).ok();
+    }
+    key
+}
+
+fn calculate_checksum_with_seed(checksum_seed: &[u8], json: &impl AsRef<[u8]>) -> String {
+    let mut summer = Sha256::new();
+    summer.update(checksum_seed);
+    summer.update(json);
+    summer.update(checksum_seed);
+    let mut checksum = String::new();
+    for byte in summer.finalize().as_slice() {
+        use std::fmt::Write;
+        write!(&mut checksum, "{:02x}", byte).unwrap();
+    }
+
+    checksum
+}
+
+fn is_well_formed_checksum(checksum: &str) -> bool {
+    checksum.len() == 64
+        && checksum
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_telemetry(
+        cx: &mut gpui::TestAppContext,
+        http_client: Arc<HttpClientWithUrl>,
+    ) -> Arc<Telemetry> {
+        test_telemetry_with_clock(cx, Arc::new(clock::FakeSystemClock::new()), http_client)
+    }
+
+    fn test_telemetry_with_clock(
+        cx: &mut gpui::TestAppContext,
+        clock: Arc<clock::FakeSystemClock>,
+        http_client: Arc<HttpClientWithUrl>,
+    ) -> Arc<Telemetry> {
+        cx.update(|cx| {
+            cx.set_global(SettingsStore::test(cx));
+            crate::init_settings(cx);
+            Telemetry::new_internal(clock, http_client, false, cx)
+        })
+    }
+
+    fn updated_entries(entries: &[(&str, PathChange)]) -> UpdatedEntriesSet {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(id, (path, change))| {
+                (
+                    Arc::from(Path::new(path)),
+                    worktree::ProjectEntryId::from_proto(id as u64),
+                    *change,
+                )
+            })
+            .collect()
+    }
+
+    fn added_entries(paths: &[&str]) -> UpdatedEntriesSet {
+        updated_entries(
+            &paths
+                .iter()
+                .map(|path| (*path, PathChange::Added))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_os_name_is_stable() {
+        assert_eq!(os_name(), os_name());
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let ubuntu = r#"PRETTY_NAME="Ubuntu 22.04.4 LTS"
+NAME="Ubuntu"
+VERSION_ID="22.04"
+VERSION="22.04.4 LTS (Jammy Jellyfish)"
+VERSION_CODENAME=jammy
+ID=ubuntu
+ID_LIKE=debian"#;
+        assert_eq!(parse_os_release(ubuntu), "ubuntu 22.04 (jammy)");
+
+        let arch = r#"NAME="Arch Linux"
+PRETTY_NAME="Arch Linux"
+ID=arch
+BUILD_ID=rolling
+ANSI_COLOR="38;2;23;147;209""#;
+        assert_eq!(parse_os_release(arch), "Arch Linux");
+
+        let fedora = "ID=fedora\nVERSION_ID=40\n";
+        assert_eq!(parse_os_release(fedora), "fedora 40");
+
+        assert_eq!(parse_os_release("ID=minimal"), "minimal unknown");
+        assert_eq!(parse_os_release(""), "unknown unknown");
+    }
+
+    #[test]
+    fn test_assistant_event_properties_omit_missing_usage() {
+        let event = AssistantEventData {
+            conversation_id: None,
+            message_id: None,
+            kind: telemetry_events::AssistantKind::Panel,
+            phase: AssistantPhase::Response,
+            model: "model".to_string(),
+            model_provider: "provider".to_string(),
+            response_latency: None,
+            error_message: None,
+            language_name: None,
+            input_tokens: None,
+            output_tokens: Some(42),
+            cost_usd: None,
+        };
+
+        let properties = assistant_event_properties(&event);
+        assert_eq!(properties["model"], "model");
+        assert_eq!(properties["output_tokens"], 42);
+        assert!(properties.contains_key("error_message"));
+        assert!(!properties.contains_key("input_tokens"));
+        assert!(!properties.contains_key("cost_usd"));
+    }
+
+    #[gpui::test]
+    fn test_memory_sink(cx: &mut gpui::TestAppContext) {
+        let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response());
+        let event = |event_type: &str| {
+            Event::Flexible(telemetry_events::FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::from_iter([("project_type".to_string(), "rust".into())]),
+            })
+        };
+
+        telemetry.report_event(event("Project Opened"));
+        assert!(telemetry.recorded_events().is_empty());
+
+        let telemetry = telemetry.with_memory_sink();
+        telemetry.report_event(event("Project Opened"));
+        telemetry.report_event(event("Project Opened"));
+        assert_eq!(
+            telemetry.recorded_events(),
+            [event("Project Opened"), event("Project Opened")]
+        );
+    }
+
+    #[gpui::test]
+    fn test_settings_changed_events(cx: &mut gpui::TestAppContext) {
+        let telemetry =
+            test_telemetry(cx, http_client::FakeHttpClient::with_404_response()).with_memory_sink();
+        let set_user_settings = |cx: &mut gpui::TestAppContext, settings: &str| {
+            cx.update(|cx| {
+                cx.update_global::<SettingsStore, _>(|store, cx| {
+                    store.set_user_settings(settings, cx).unwrap();
+                })
+            });
+        };
+        let settings_changed = |key: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: "Settings Changed".to_string(),
+                event_properties: HashMap::from_iter([("key".to_string(), key.into())]),
+            })
+        };
+
+        set_user_settings(cx, r#"{"vim_mode": true, "unreported_setting": "secret"}"#);
+        assert_eq!(telemetry.recorded_events(), [settings_changed("vim_mode")]);
+
+        set_user_settings(
+            cx,
+            r#"{"vim_mode": true, "tab_size": 2, "unreported_setting": "changed"}"#,
+        );
+        assert_eq!(
+            telemetry.recorded_events(),
+            [settings_changed("vim_mode"), settings_changed("tab_size")]
+        );
+    }
+
+    #[gpui::test]
+    fn test_close_periods_reports_assistant_responses(cx: &mut gpui::TestAppContext) {
+        let telemetry =
+            test_telemetry(cx, http_client::FakeHttpClient::with_404_response()).with_memory_sink();
+        let response = AssistantEventData {
+            conversation_id: Some("conversation".to_string()),
+            message_id: None,
+            kind: telemetry_events::AssistantKind::Panel,
+            phase: AssistantPhase::Response,
+            model: "model".to_string(),
+            model_provider: "provider".to_string(),
+            response_latency: Some(Duration::from_millis(100)),
+            error_message: None,
+            language_name: None,
+            input_tokens: Some(10),
+            output_tokens: Some(20),
+            cost_usd: Some(0.25),
+        };
+
+        telemetry.report_assistant_event(response.clone());
+        telemetry.report_assistant_event(response);
+        assert!(telemetry.recorded_events().is_empty());
+
+        telemetry.close_periods();
+        let events = telemetry.recorded_events();
+        let [Event::Flexible(event)] = events.as_slice() else {
+            panic!("expected a single flexible event, got {events:?}");
+        };
+        assert_eq!(event.event_type, "Assistant Responded");
+        assert_eq!(event.event_properties["response_count"], 2);
+        assert_eq!(event.event_properties["input_tokens"], 20);
+        assert_eq!(event.event_properties["output_tokens"], 40);
+        assert_eq!(event.event_properties["cost_usd"], 0.5);
+
+        telemetry.close_periods();
+        assert_eq!(telemetry.recorded_events().len(), 1);
+    }
+
+    #[gpui::test]
+    fn test_report_update_failure(cx: &mut gpui::TestAppContext) {
+        let telemetry =
+            test_telemetry(cx, http_client::FakeHttpClient::with_404_response()).with_memory_sink();
+
+        telemetry.report_update_failure("checksum", "checksum mismatch");
+        assert_eq!(
+            telemetry.recorded_events(),
+            [Event::Flexible(FlexibleEvent {
+                event_type: "Update Failed".to_string(),
+                event_properties: HashMap::from_iter([
+                    ("category".to_string(), "checksum".into()),
+                    ("message".to_string(), "checksum mismatch".into()),
+                ]),
+            })]
+        );
+        assert_eq!(telemetry.stats().events_queued, 0);
+    }
+
+    #[gpui::test]
+    fn test_report_extension_loaded(cx: &mut gpui::TestAppContext) {
+        let telemetry =
+            test_telemetry(cx, http_client::FakeHttpClient::with_404_response()).with_memory_sink();
+        let event = |name: &str, version: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: "Extension Loaded".to_string(),
+                event_properties: HashMap::from_iter([
+                    ("extension_id".to_string(), name.into()),
+                    ("version".to_string(), version.into()),
+                ]),
+            })
+        };
+
+        telemetry.report_extension_loaded("zig", "0.1.0");
+        telemetry.report_extension_loaded("zig", "0.1.1");
+        telemetry.report_extension_loaded("gleam", "0.2.0");
+        assert_eq!(
+            telemetry.recorded_events(),
+            [event("zig", "0.1.0"), event("gleam", "0.2.0")]
+        );
+        // Metrics are off by default, so nothing is queued to be sent.
+        assert_eq!(telemetry.stats().events_queued, 0);
+    }
+
+    #[gpui::test]
+    fn test_event_timestamps_never_go_backward(cx: &mut gpui::TestAppContext) {
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let telemetry = test_telemetry_with_clock(
+            cx,
+            clock.clone(),
+            http_client::FakeHttpClient::with_404_response(),
+        );
+        telemetry.state.lock().settings.metrics = true;
+        let event = |event_type: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        let start = clock.utc_now();
+        telemetry.report_event(event("First"));
+        clock.advance(Duration::from_secs(5));
+        telemetry.report_event(event("Second"));
+        clock.set_now(start + Duration::from_secs(2));
+        telemetry.report_event(event("Third"));
+        clock.set_now(start + Duration::from_secs(6));
+        telemetry.report_event(event("Fourth"));
+
+        let timestamps = telemetry
+            .state
+            .lock()
+            .events_queue
+            .iter()
+            .map(|event| {
+                (
+                    event.milliseconds_since_first_event,
+                    event.clock_moved_backward,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timestamps,
+            [(0, false), (5000, false), (5000, true), (6000, false)]
+        );
+    }
+
+    #[gpui::test]
+    fn test_environment_tag_is_attached_to_events(cx: &mut gpui::TestAppContext) {
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let telemetry = test_telemetry_with_clock(
+            cx,
+            clock.clone(),
+            http_client::FakeHttpClient::with_404_response(),
+        );
+        telemetry.state.lock().settings.metrics = true;
+        let event = || {
+            Event::Flexible(FlexibleEvent {
+                event_type: "Tagged".to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        telemetry.report_event(event());
+        telemetry.state.lock().settings.environment_tag = Some("ci".to_string());
+        telemetry.report_event(event());
+
+        let state = telemetry.state.lock();
+        let tags = state
+            .events_queue
+            .iter()
+            .map(|event| event.environment_tag.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(tags, [None, Some("ci")]);
+
+        let untagged = serde_json::to_value(&state.events_queue[0]).unwrap();
+        assert!(untagged.get("environment_tag").is_none());
+        let tagged = serde_json::to_value(&state.events_queue[1]).unwrap();
+        assert_eq!(tagged["environment_tag"], "ci");
+    }
+
+    #[gpui::test]
+    fn test_project_settings_disable_metrics(cx: &mut gpui::TestAppContext) {
+        let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response());
+        let sensitive_worktree = WorktreeId::from_usize(1);
+        let other_worktree = WorktreeId::from_usize(2);
+
+        cx.update(|cx| {
+            cx.update_global::<SettingsStore, _>(|store, cx| {
+                for (worktree_id, metrics) in [(sensitive_worktree, false), (other_worktree, true)]
+                {
+                    store
+                        .set_local_settings(
+                            worktree_id,
+                            Path::new("").into(),
+                            settings::LocalSettingsKind::Settings,
+                            Some(&format!(r#"{{"telemetry": {{"metrics": {metrics}}}}}"#)),
+                            cx,
+                        )
+                        .unwrap();
+                }
+            });
+        });
+
+        assert_eq!(
+            telemetry.state.lock().worktrees_with_metrics_disabled,
+            HashSet::from_iter([sensitive_worktree])
+        );
+    }
+
+    #[gpui::test]
+    async fn test_flush_circuit_breaker(cx: &mut gpui::TestAppContext) {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let collector_is_up = Arc::new(AtomicBool::new(false));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let http_client = http_client::FakeHttpClient::create({
+            let collector_is_up = collector_is_up.clone();
+            let requests = requests.clone();
+            move |_| {
+                requests.fetch_add(1, SeqCst);
+                let status = if collector_is_up.load(SeqCst) {
+                    200
+                } else {
+                    503
+                };
+                async move {
+                    Ok(http_client::Response::builder()
+                        .status(status)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry_with_clock(cx, clock.clone(), http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.installation_id = Some("installation".into());
+            state.max_queue_size = 10;
+        }
+        let report = |i: usize| {
+            telemetry.report_event(Event::Flexible(FlexibleEvent {
+                event_type: format!("Event {i}"),
+                event_properties: HashMap::default(),
+            }));
+        };
+        let circuit = || telemetry.state.lock().flush_circuit;
+
+        for i in 0..FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT {
+            assert!(matches!(circuit(), FlushCircuit::Closed { .. }));
+            report(i);
+            assert!(telemetry.flush_events().await.is_err());
+        }
+        assert!(matches!(circuit(), FlushCircuit::Open { .. }));
+        assert_eq!(requests.load(SeqCst), FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT);
+
+        // The events of failed flushes are kept for a later attempt. While open, flushes are
+        // skipped and events stay queued.
+        report(100);
+        assert_eq!(telemetry.flush_events().await.unwrap(), 0);
+        assert_eq!(requests.load(SeqCst), FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT);
+        assert_eq!(
+            telemetry.stats().events_queued,
+            FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT + 1
+        );
+
+        // After the cooldown, a single flush tests the collector, which is still down.
+        clock.advance(FLUSH_CIRCUIT_COOLDOWN);
+        cx.run_until_parked();
+        assert_eq!(
+            requests.load(SeqCst),
+            FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT + 1
+        );
+        assert!(matches!(circuit(), FlushCircuit::Open { .. }));
+
+        report(101);
+        collector_is_up.store(true, SeqCst);
+        clock.advance(FLUSH_CIRCUIT_COOLDOWN);
+        assert!(
+            telemetry
+                .state
+                .lock()
+                .flush_circuit
+                .allows_flush(clock.utc_now())
+        );
+        assert_eq!(circuit(), FlushCircuit::HalfOpen);
+        assert_eq!(
+            telemetry.flush_events().await.unwrap(),
+            FLUSH_FAILURES_BEFORE_OPENING_CIRCUIT + 2
+        );
+        assert_eq!(
+            circuit(),
+            FlushCircuit::Closed {
+                consecutive_failures: 0
+            }
+        );
+        assert_eq!(telemetry.stats().events_queued, 0);
+    }
+
+    #[gpui::test]
+    fn test_flush_interval_follows_clock(cx: &mut gpui::TestAppContext) {
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let telemetry = test_telemetry_with_clock(
+            cx,
+            clock.clone(),
+            http_client::FakeHttpClient::with_200_response(),
+        );
+        let flush_interval = Duration::from_secs(60);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.installation_id = Some("installation".into());
+            state.flush_interval = flush_interval;
+            state.max_queue_size = 10;
+        }
+
+        telemetry.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Project Opened".to_string(),
+            event_properties: HashMap::default(),
+        }));
+        assert_eq!(telemetry.stats().events_queued, 1);
+
+        clock.advance(flush_interval - Duration::from_secs(1));
+        cx.run_until_parked();
+        assert_eq!(telemetry.stats().events_queued, 1);
+
+        clock.advance(Duration::from_secs(1));
+        cx.run_until_parked();
+        assert_eq!(telemetry.stats().events_queued, 0);
+        assert_eq!(telemetry.stats().events_flushed, 1);
+    }
+
+    #[gpui::test]
+    async fn test_events_flushed_counts_accepted_events(cx: &mut gpui::TestAppContext) {
+        use futures::AsyncReadExt as _;
+
+        let installation_ids = Arc::new(Mutex::new(Vec::new()));
+        let status = Arc::new(Mutex::new(500));
+        let http_client = http_client::FakeHttpClient::create({
+            let installation_ids = installation_ids.clone();
+            let status = status.clone();
+            move |request| {
+                let installation_ids = installation_ids.clone();
+                let status = *status.lock();
+                async move {
+                    let mut body = Vec::new();
+                    request.into_body().read_to_end(&mut body).await?;
+                    let body: EventRequestBody = serde_json::from_slice(&body)?;
+                    installation_ids.lock().push(body.installation_id);
+                    Ok(http_client::Response::builder()
+                        .status(status)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.installation_id = Some("installation".into());
+            state.max_queue_size = 10;
+            state.compress_telemetry = false;
+        }
+
+        telemetry.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Project Opened".to_string(),
+            event_properties: HashMap::default(),
+        }));
+        assert!(telemetry.flush_events().await.is_err());
+        assert_eq!(telemetry.stats().events_flushed, 0);
+        assert_eq!(telemetry.stats().events_queued, 1);
+
+        *status.lock() = 200;
+        assert_eq!(telemetry.flush_events().await.unwrap(), 1);
+        assert_eq!(telemetry.stats().events_flushed, 1);
+        assert_eq!(telemetry.stats().events_queued, 0);
+        let installation_id = Some("installation".to_string());
+        assert_eq!(
+            *installation_ids.lock(),
+            [installation_id.clone(), installation_id]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_flush_without_start(cx: &mut gpui::TestAppContext) {
+        let requests = Arc::new(Mutex::new(0));
+        let http_client = http_client::FakeHttpClient::create({
+            let requests = requests.clone();
+            move |_| {
+                *requests.lock() += 1;
+                async move {
+                    Ok(http_client::Response::builder()
+                        .status(200)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.max_queue_size = 10;
+        }
+
+        telemetry.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Project Opened".to_string(),
+            event_properties: HashMap::default(),
+        }));
+        let error = telemetry.flush_events().await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<telemetry_events::MissingFieldsError>(),
+            Some(&telemetry_events::MissingFieldsError {
+                missing_fields: vec!["installation_id"],
+            })
+        );
+        assert_eq!(telemetry.stats().events_queued, 1);
+        assert_eq!(*requests.lock(), 0);
+
+        telemetry.set_anonymous(true);
+        assert_eq!(telemetry.flush_events().await.unwrap(), 1);
+        assert_eq!(telemetry.stats().events_queued, 0);
+        assert_eq!(*requests.lock(), 1);
+    }
+
+    #[gpui::test]
+    async fn test_flush_splits_large_batches(cx: &mut gpui::TestAppContext) {
+        use futures::AsyncReadExt as _;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let http_client = http_client::FakeHttpClient::create({
+            let requests = requests.clone();
+            move |request| {
+                let requests = requests.clone();
+                async move {
+                    let mut body = Vec::new();
+                    request.into_body().read_to_end(&mut body).await?;
+                    let body: EventRequestBody = serde_json::from_slice(&body)?;
+                    requests.lock().push(body.events.len());
+                    Ok(http_client::Response::builder()
+                        .status(200)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.max_queue_size = 10;
+            state.max_batch_bytes = 2048;
+            state.compress_telemetry = false;
+            state.installation_id = Some("installation".into());
+        }
+
+        for i in 0..3 {
+            telemetry.report_event(Event::Flexible(FlexibleEvent {
+                event_type: format!("Large Event {i}"),
+                event_properties: HashMap::from_iter([(
+                    "payload".to_string(),
+                    "x".repeat(1500).into(),
+                )]),
+            }));
+        }
+        telemetry.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Small Event".to_string(),
+            event_properties: HashMap::default(),
+        }));
+
+        assert_eq!(telemetry.flush_events().await.unwrap(), 4);
+        assert_eq!(*requests.lock(), [1, 1, 2]);
+    }
+
+    #[gpui::test]
+    async fn test_snapshot(cx: &mut gpui::TestAppContext) {
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let telemetry = test_telemetry_with_clock(
+            cx,
+            clock.clone(),
+            http_client::FakeHttpClient::create(|_| async move {
+                Ok(http_client::Response::builder()
+                    .status(200)
+                    .body(AsyncBody::default())?)
+            }),
+        );
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.installation_id = Some("installation".into());
+            state.max_queue_size = 10;
+        }
+
+        telemetry.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "Project Opened".to_string(),
+            event_properties: HashMap::default(),
+        }));
+        let snapshot = telemetry.snapshot();
+        assert!(snapshot.settings.metrics);
+        assert!(!snapshot.disabled);
+        assert_eq!(snapshot.last_flushed_at, None);
+        assert_eq!(snapshot.stats.events_queued, 1);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(telemetry.flush_events().await.unwrap(), 1);
+        let snapshot = telemetry.snapshot();
+        assert_eq!(snapshot.last_flushed_at, Some(clock.utc_now()));
+        assert_eq!(snapshot.stats.events_queued, 0);
+        assert_eq!(snapshot.stats.events_flushed, 1);
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["settings"]["metrics"], true);
+        assert_eq!(json["stats"]["events_flushed"], 1);
+    }
+
+    #[gpui::test]
+    fn test_os_version_expires(cx: &mut gpui::TestAppContext) {
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let telemetry = test_telemetry_with_clock(
+            cx,
+            clock.clone(),
+            http_client::FakeHttpClient::with_404_response(),
+        );
+
+        telemetry.refresh_os_version();
+        assert_eq!(telemetry.state.lock().os_version, Some(os_version()));
+
+        telemetry.state.lock().os_version = Some("before upgrade".to_string());
+        clock.advance(OS_VERSION_TTL - Duration::from_secs(1));
+        telemetry.refresh_os_version();
+        assert_eq!(
+            telemetry.state.lock().os_version.as_deref(),
+            Some("before upgrade")
+        );
+
+        clock.advance(Duration::from_secs(1));
+        telemetry.refresh_os_version();
+        assert_eq!(telemetry.state.lock().os_version, Some(os_version()));
+    }
+
+    #[gpui::test]
+    async fn test_ping_endpoint(cx: &mut gpui::TestAppContext) {
+        let new_telemetry = |http_client, cx: &mut gpui::TestAppContext| {
+            let telemetry = test_telemetry(cx, http_client);
+            telemetry.state.lock().telemetry_endpoint =
+                Some("https://collector.test.example/events".to_string());
+            telemetry
+        };
+
+        let telemetry = new_telemetry(
+            http_client::FakeHttpClient::create(|request| async move {
+                assert_eq!(request.method(), Method::HEAD);
+                assert_eq!(
+                    request.uri().to_string(),
+                    "https://collector.test.example/events"
+                );
+                Ok(http_client::Response::builder()
+                    .status(405)
+                    .body(AsyncBody::default())?)
+            }),
+            cx,
+        );
+        telemetry.ping_endpoint().await.unwrap();
+
+        let telemetry = new_telemetry(http_client::FakeHttpClient::with_404_response(), cx);
+        assert!(telemetry.ping_endpoint().await.is_err());
+    }
+
+    #[gpui::test]
+    async fn test_allowed_hosts(cx: &mut gpui::TestAppContext) {
+        let requests = Arc::new(Mutex::new(0));
+        let http_client = http_client::FakeHttpClient::create({
+            let requests = requests.clone();
+            move |_| {
+                let requests = requests.clone();
+                async move {
+                    *requests.lock() += 1;
+                    Ok(http_client::Response::builder()
+                        .status(200)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.max_queue_size = 10;
+            state.installation_id = Some("installation".into());
+            state.telemetry_endpoint = Some("https://collector.test.example/events".to_string());
+            state.allowed_hosts = vec!["Collector.Test.Example".to_string()];
+        }
+        let event = || {
+            Event::Flexible(FlexibleEvent {
+                event_type: "Event".to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        telemetry.report_event(event());
+        assert_eq!(telemetry.flush_events().await.unwrap(), 1);
+        assert_eq!(*requests.lock(), 1);
+
+        telemetry.state.lock().telemetry_endpoint =
+            Some("https://elsewhere.test.example/events".to_string());
+        telemetry.report_event(event());
+        let error = telemetry.flush_events().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "refusing to send a request to elsewhere.test.example, as it isn't in `allowed_hosts`"
+        );
+        assert_eq!(*requests.lock(), 1);
+    }
+
+    #[gpui::test]
+    async fn test_rate_limited_flush_rewrites_spool(cx: &mut gpui::TestAppContext) {
+        let dir = tempfile::tempdir().unwrap();
+        let spool_path = dir.path().join("telemetry_spool.jsonl");
+        std::fs::write(&spool_path, "").unwrap();
+        let log_path = dir.path().join("telemetry.log");
+
+        let requests = Arc::new(Mutex::new(0));
+        let http_client = http_client::FakeHttpClient::create({
+            let requests = requests.clone();
+            move |_| {
+                let mut requests = requests.lock();
+                *requests += 1;
+                let status = if *requests == 2 { 429 } else { 200 };
+                async move {
+                    Ok(http_client::Response::builder()
+                        .status(status)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.installation_id = Some("installation".into());
+            state.max_queue_size = 10;
+            state.max_batch_bytes = 1;
+            state.compress_telemetry = false;
+            state.log_file = Some(File::create(&log_path).unwrap());
+        }
+        telemetry.replay_spool(spool_path.clone()).unwrap();
+        let event_types = |events: Vec<EventWrapper>| {
+            events
+                .into_iter()
+                .map(|event| match event.event {
+                    Event::Flexible(event) => event.event_type,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let logged_events = || {
+            std::fs::read_to_string(&log_path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<EventWrapper>(line).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        for event_type in ["First", "Second"] {
+            telemetry.report_event(Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            }));
+        }
+        cx.run_until_parked();
+
+        // The first batch is accepted before the second is rate limited, so only the second
+        // stays spooled.
+        assert_eq!(telemetry.flush_events().await.unwrap(), 1);
+        assert_eq!(event_types(read_spool(&spool_path).unwrap()), ["Second"]);
+        assert_eq!(event_types(logged_events()), ["First"]);
+
+        assert_eq!(telemetry.flush_events().await.unwrap(), 1);
+        assert!(read_spool(&spool_path).unwrap().is_empty());
+        assert_eq!(event_types(logged_events()), ["First", "Second"]);
+    }
+
+    #[gpui::test]
+    async fn test_replay_spool(cx: &mut gpui::TestAppContext) {
+        use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+
+        let dir = tempfile::tempdir().unwrap();
+        let spool_path = dir.path().join("telemetry_spool.jsonl");
+        let spooled_event = |event_type: &str| EventWrapper {
+            signed_in: false,
+            milliseconds_since_first_event: 0,
+            clock_moved_backward: false,
+            environment_tag: None,
+            event: Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            }),
+        };
+        let mut leftover = Vec::new();
+        for event_type in ["Before Crash 1", "Before Crash 2"] {
+            serde_json::to_writer(&mut leftover, &spooled_event(event_type)).unwrap();
+            leftover.push(b'\n');
+        }
+        leftover.extend_from_slice(b"{\"signed_in\": fa");
+        std::fs::write(&spool_path, leftover).unwrap();
+
+        let collector_is_up = Arc::new(AtomicBool::new(false));
+        let telemetry = test_telemetry(
+            cx,
+            http_client::FakeHttpClient::create({
+                let collector_is_up = collector_is_up.clone();
+                move |_| {
+                    let status = if collector_is_up.load(SeqCst) {
+                        200
+                    } else {
+                        503
+                    };
+                    async move {
+                        Ok(http_client::Response::builder()
+                            .status(status)
+                            .body(AsyncBody::default())?)
+                    }
+                }
+            }),
+        );
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.max_queue_size = 10;
+            state.installation_id = Some("installation".into());
+        }
+
+        assert_eq!(telemetry.replay_spool(spool_path.clone()).unwrap(), 2);
+        telemetry.report_event(Event::Flexible(FlexibleEvent {
+            event_type: "After Restart".to_string(),
+            event_properties: HashMap::default(),
+        }));
+        cx.run_until_parked();
+        let event_types = |events: Vec<EventWrapper>| {
+            events
+                .into_iter()
+                .map(|event| match event.event {
+                    Event::Flexible(event) => event.event_type,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            event_types(read_spool(&spool_path).unwrap()),
+            ["Before Crash 1", "Before Crash 2", "After Restart"]
+        );
+        assert_eq!(
+            event_types(telemetry.state.lock().events_queue.clone()),
+            ["Before Crash 1", "Before Crash 2", "After Restart"]
+        );
+
+        // Events stay queued and spooled until the collector accepts them.
+        assert!(telemetry.flush_events().await.is_err());
+        assert_eq!(
+            event_types(read_spool(&spool_path).unwrap()),
+            ["Before Crash 1", "Before Crash 2", "After Restart"]
+        );
+        assert_eq!(telemetry.stats().events_queued, 3);
+
+        collector_is_up.store(true, SeqCst);
+        assert_eq!(telemetry.flush_events().await.unwrap(), 3);
+        assert_eq!(std::fs::read_to_string(&spool_path).unwrap(), "");
+    }
+
+    #[gpui::test]
+    async fn test_gzip_negotiation(cx: &mut gpui::TestAppContext) {
+        use futures::AsyncReadExt as _;
+
+        let head_requests = Arc::new(Mutex::new(0));
+        let content_encodings = Arc::new(Mutex::new(Vec::new()));
+        let http_client = http_client::FakeHttpClient::create({
+            let head_requests = head_requests.clone();
+            let content_encodings = content_encodings.clone();
+            move |request| {
+                let head_requests = head_requests.clone();
+                let content_encodings = content_encodings.clone();
+                async move {
+                    if request.method() == Method::HEAD {
+                        *head_requests.lock() += 1;
+                        return Ok(http_client::Response::builder()
+                            .status(405)
+                            .header("Accept-Encoding", "br, gzip;q=0.5")
+                            .body(AsyncBody::default())?);
+                    }
+                    let content_encoding = request
+                        .headers()
+                        .get("Content-Encoding")
+                        .map(|value| value.to_str().unwrap().to_string());
+                    let mut body = Vec::new();
+                    request.into_body().read_to_end(&mut body).await?;
+                    if content_encoding.is_none() {
+                        serde_json::from_slice::<EventRequestBody>(&body)?;
+                    }
+                    content_encodings.lock().push(content_encoding);
+                    Ok(http_client::Response::builder()
+                        .status(200)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.max_queue_size = 10;
+            state.installation_id = Some("installation".into());
+            state.telemetry_endpoint = Some("https://collector.test.example/events".to_string());
+            state.settings.server_accepts_gzip = Some(false);
+        }
+        let flush_large_event = || {
+            telemetry.report_event(Event::Flexible(FlexibleEvent {
+                event_type: "Large Event".to_string(),
+                event_properties: HashMap::from_iter([(
+                    "payload".to_string(),
+                    "x".repeat(COMPRESSION_THRESHOLD_BYTES).into(),
+                )]),
+            }));
+            telemetry.flush_events()
+        };
+
+        assert_eq!(flush_large_event().await.unwrap(), 1);
+        assert_eq!(*content_encodings.lock(), [None]);
+        assert_eq!(*head_requests.lock(), 0);
+
+        // Without the setting, the endpoint is asked once and the answer is reused.
+        telemetry.state.lock().settings.server_accepts_gzip = None;
+        assert_eq!(flush_large_event().await.unwrap(), 1);
+        assert_eq!(flush_large_event().await.unwrap(), 1);
+        assert_eq!(
+            *content_encodings.lock(),
+            [None, Some("gzip".to_string()), Some("gzip".to_string())]
+        );
+        assert_eq!(*head_requests.lock(), 1);
+    }
+
+    #[gpui::test]
+    async fn test_flush_respects_retry_after(cx: &mut gpui::TestAppContext) {
+        use futures::AsyncReadExt as _;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let http_client = http_client::FakeHttpClient::create({
+            let requests = requests.clone();
+            move |request| {
+                let requests = requests.clone();
+                async move {
+                    let mut body = Vec::new();
+                    request.into_body().read_to_end(&mut body).await?;
+                    let body: EventRequestBody = serde_json::from_slice(&body)?;
+                    let mut requests = requests.lock();
+                    requests.push(body.events.len());
+                    let response = if requests.len() == 1 {
+                        http_client::Response::builder()
+                            .status(429)
+                            .header("Retry-After", "30")
+                    } else {
+                        http_client::Response::builder().status(200)
+                    };
+                    Ok(response.body(AsyncBody::default())?)
+                }
+            }
+        });
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let telemetry = test_telemetry_with_clock(cx, clock.clone(), http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.installation_id = Some("installation".into());
+            state.max_queue_size = 2;
+            state.compress_telemetry = false;
+            state.session_id = Some("session".to_string());
+        }
+        let event = |event_type: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        telemetry.report_event(event("First"));
+        telemetry.report_event(event("Second"));
+        assert_eq!(telemetry.flush_events().await.unwrap(), 0);
+        assert_eq!(*requests.lock(), [2]);
+        assert_eq!(telemetry.stats().events_queued, 2);
+
+        // While backing off, the queue is capped rather than flushed early.
+        telemetry.report_event(event("Third"));
+        assert_eq!(telemetry.stats().events_queued, 2);
+        assert_eq!(telemetry.stats().events_dropped, 1);
+
+        clock.advance(Duration::from_secs(29));
+        cx.run_until_parked();
+        assert_eq!(*requests.lock(), [2]);
+
+        clock.advance(Duration::from_secs(1));
+        cx.run_until_parked();
+        assert_eq!(*requests.lock(), [2, 2]);
+        assert_eq!(telemetry.stats().events_queued, 0);
+
+        assert_eq!(
+            parse_retry_after(Some(" 120 ")),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(Some("999999")), Some(MAX_RETRY_AFTER));
+        assert_eq!(
+            parse_retry_after(Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            None
+        );
+        assert_eq!(parse_retry_after(None), None);
+    }
+
+    #[gpui::test]
+    async fn test_exclude_session_id(cx: &mut gpui::TestAppContext) {
+        use futures::AsyncReadExt as _;
+
+        let request_bodies = Arc::new(Mutex::new(Vec::new()));
+        let http_client = http_client::FakeHttpClient::create({
+            let request_bodies = request_bodies.clone();
+            move |request| {
+                let request_bodies = request_bodies.clone();
+                async move {
+                    let mut body = Vec::new();
+                    request.into_body().read_to_end(&mut body).await?;
+                    let body: serde_json::Value = serde_json::from_slice(&body)?;
+                    request_bodies.lock().push(body);
+                    Ok(http_client::Response::builder()
+                        .status(200)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+        let telemetry = test_telemetry(cx, http_client);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.compress_telemetry = false;
+            state.session_id = Some("session".to_string());
+            state.installation_id = Some("installation".into());
+        }
+        let event = |event_type: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        telemetry.report_event(event("First"));
+        telemetry.flush_events().await.unwrap();
+        telemetry.state.lock().settings.include_session_id = false;
+        telemetry.report_event(event("Second"));
+        telemetry.flush_events().await.unwrap();
+
+        let request_bodies = request_bodies.lock();
+        assert_eq!(request_bodies[0]["session_id"], "session");
+        assert!(request_bodies[1].get("session_id").is_none());
+    }
+
+    #[gpui::test]
+    fn test_disabled_via_env_var(cx: &mut gpui::TestAppContext) {
+        let telemetry = cx.update(|cx| {
+            cx.set_global(SettingsStore::test(cx));
+            crate::init_settings(cx);
+            Telemetry::new_internal(
+                Arc::new(clock::FakeSystemClock::new()),
+                http_client::FakeHttpClient::with_404_response(),
+                true,
+                cx,
+            )
+        });
+        assert!(!telemetry.metrics_enabled());
+
+        let telemetry = telemetry.with_memory_sink();
+        telemetry.report_event(Event::Flexible(telemetry_events::FlexibleEvent {
+            event_type: "Project Opened".to_string(),
+            event_properties: HashMap::default(),
+        }));
+        assert!(telemetry.recorded_events().is_empty());
+        assert_eq!(telemetry.stats(), TelemetryStats::default());
+    }
+
+    #[test]
+    fn test_format_user_agent() {
+        assert_eq!(
+            format_user_agent("1.2.3", "macOS", "aarch64"),
+            "Fred/1.2.3 (macOS; aarch64)"
+        );
+    }
+
+    #[test]
+    fn test_event_request_body_builder_requires_fields() {
+        let error = EventRequestBody::builder()
+            .os_name("macOS")
+            .build()
+            .unwrap_err();
+        assert_eq!(error.missing_fields, ["installation_id", "app_version"]);
+        assert_eq!(
+            error.to_string(),
+            "event request body is missing required fields: installation_id, app_version"
+        );
+
+        let body = EventRequestBody::builder()
+            .anonymous(true)
+            .app_version("1.2.3")
+            .os_name("macOS")
+            .build()
+            .unwrap();
+        assert_eq!(body.installation_id, None);
+        assert_eq!(body.architecture, env::consts::ARCH);
+
+        assert!(
+            EventRequestBody::builder()
+                .installation_id(Some("installation".to_string()))
+                .app_version("1.2.3")
+                .os_name("macOS")
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_changed_settings() {
+        let old = reported_settings(&serde_json::json!({
+            "vim_mode": false,
+            "theme": "One Dark",
+            "tab_size": 4,
+        }));
+        let new = reported_settings(&serde_json::json!({
+            "vim_mode": true,
+            "theme": "One Dark",
+            "buffer_font_size": 14,
+            "unreported_setting": "secret",
+        }));
+
+        assert_eq!(
+            changed_settings(&old, &new),
+            ["buffer_font_size", "tab_size", "vim_mode"]
+        );
+        assert!(changed_settings(&new, &new).is_empty());
+        assert!(!new.contains_key("unreported_setting"));
+    }
+
+    #[test]
+    fn test_recent_event_filter() {
+        let event = |event_type: &str, path: &str| {
+            Event::Flexible(telemetry_events::FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::from_iter([
+                    ("project_type".to_string(), "rust".into()),
+                    ("path".to_string(), path.into()),
+                ]),
+            })
+        };
+        let mut filter = RecentEventFilter::new(2);
+
+        assert!(!filter.is_duplicate(&event("Project Opened", "a")));
+        assert!(filter.is_duplicate(&event("Project Opened", "a")));
+        assert!(!filter.is_duplicate(&event("Project Opened", "b")));
+        assert!(!filter.is_duplicate(&event("Project Closed", "a")));
+        // The first event has now left the window
+        assert!(!filter.is_duplicate(&event("Project Opened", "a")));
+
+        assert!(!filter.is_duplicate(&event("Editor Edited", "a")));
+        assert!(!filter.is_duplicate(&event("Editor Edited", "a")));
+
+        let mut disabled = RecentEventFilter::new(0);
+        assert!(!disabled.is_duplicate(&event("Project Opened", "a")));
+        assert!(!disabled.is_duplicate(&event("Project Opened", "a")));
+    }
+
+    #[test]
+    fn test_should_sample_event() {
+        use telemetry_events::FlexibleEvent;
+
+        let event = |event_type: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            assert!(!should_sample_event(&event("Editor Edited"), 0.0, &mut rng));
+            assert!(should_sample_event(
+                &event("Assistant Responded"),
+                0.0,
+                &mut rng
+            ));
+            assert!(should_sample_event(&event("Project Opened"), 0.0, &mut rng));
+            assert!(should_sample_event(&event("Editor Edited"), 1.0, &mut rng));
+        }
+
+        let sampled = (0..1000)
+            .filter(|_| should_sample_event(&event("Editor Edited"), 0.5, &mut rng))
+            .count();
+        assert!((400..600).contains(&sampled));
+    }
+
+    #[gpui::test]
+    fn test_report_event_sampling(cx: &mut gpui::TestAppContext) {
+        let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response())
+            .with_sampling_seed(0);
+        {
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.settings.sample_rate = 0.0;
+        }
+        let event = |event_type: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        for _ in 0..10 {
+            assert!(!telemetry.report_event(event("Editor Edited")));
+        }
+        assert!(telemetry.report_event(event("Assistant Responded")));
+        assert!(telemetry.report_event(event("Project Opened")));
+
+        let stats = telemetry.stats();
+        assert_eq!(stats.events_dropped, 10);
+        assert_eq!(stats.events_queued, 2);
+    }
+
+    #[test]
+    fn test_project_dir() {
+        assert_eq!(project_dir(Path::new("package.json")), ".");
+        assert_eq!(project_dir(Path::new("web/package.json")), "web");
+        assert_eq!(
+            project_dir(Path::new("services/api/Api.csproj")),
+            Path::new("services/api").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn test_has_new_top_level_entries() {
+        assert!(has_new_top_level_entries(&updated_entries(&[(
+            "package.json",
+            PathChange::Added
+        )])));
+        assert!(!has_new_top_level_entries(&updated_entries(&[(
+            "package.json",
+            PathChange::Updated
+        )])));
+        assert!(!has_new_top_level_entries(&updated_entries(&[(
+            "src/main.rs",
+            PathChange::Added
+        )])));
+        assert!(!has_new_top_level_entries(&updated_entries(&[])));
+    }
+
+    #[gpui::test]
+    fn test_detect_project_types(cx: &mut gpui::TestAppContext) {
+        let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response());
+        let worktree_id = WorktreeId::from_usize(1);
+        let entries = added_entries(&["mix.exs", "README.md", "composer.json", "Gemfile"]);
+
+        assert_eq!(
+            telemetry.detect_project_types(worktree_id, &entries),
+            Some(vec![
+                ("elixir", ".".to_string()),
+                ("php", ".".to_string()),
+                ("ruby", ".".to_string()),
+            ])
+        );
+        assert_eq!(
+            telemetry.detect_project_types(worktree_id, &entries),
+            Some(Vec::new())
+        );
+    }
+
+    #[gpui::test]
+    fn test_include_git_branch(cx: &mut gpui::TestAppContext) {
+        let telemetry =
+            test_telemetry(cx, http_client::FakeHttpClient::with_404_response()).with_memory_sink();
+        let entries = added_entries(&["Cargo.toml"]);
+        let git_branch = |event: &Event| match event {
+            Event::Flexible(event) => event.event_properties.get("git_branch").cloned(),
+            _ => None,
+        };
+
+        // Branches are only looked up when they'd be sent, which needs metrics on.
+        telemetry.state.lock().settings.include_git_branch = true;
+        assert!(!telemetry.include_git_branch());
+        telemetry.state.lock().settings.metrics = true;
+        assert!(telemetry.include_git_branch());
+
+        telemetry.state.lock().settings.include_git_branch = false;
+        telemetry.report_discovered_project_type_events(
+            WorktreeId::from_usize(1),
+            Path::new("/repo"),
+            Some("main"),
+            &entries,
+        );
+        telemetry.log_edit_event("editor", false, Some("main".to_string()));
+        telemetry.log_edit_event("terminal", false, None);
+
+        telemetry.state.lock().settings.include_git_branch = true;
+        telemetry.report_discovered_project_type_events(
+            WorktreeId::from_usize(2),
+            Path::new("/repo"),
+            Some("main"),
+            &entries,
+        );
+        telemetry.report_discovered_project_type_events(
+            WorktreeId::from_usize(3),
+            Path::new("/not-a-repo"),
+            None,
+            &entries,
+        );
+        telemetry.log_edit_event("editor", false, Some("main".to_string()));
+        // Closes the period of edits on `main`.
+        telemetry.log_edit_event("terminal", false, None);
+
+        let events = telemetry.recorded_events();
+        let event_types = events
+            .iter()
+            .map(|event| match event {
+                Event::Flexible(event) => event.event_type.as_str(),
+                _ => "",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            event_types,
+            [
+                "Project Opened",
+                "Editor Edited",
+                "Project Opened",
+                "Project Opened",
+                "Editor Edited",
+                "Editor Edited",
+            ]
+        );
+        assert_eq!(git_branch(&events[0]), None);
+        assert_eq!(git_branch(&events[1]), None);
+        assert_eq!(git_branch(&events[2]), Some("main".into()));
+        assert_eq!(git_branch(&events[3]), None);
+        assert_eq!(git_branch(&events[4]), None);
+        assert_eq!(git_branch(&events[5]), Some("main".into()));
+    }
+
+    #[derive(Default)]
+    struct FakeProjectEventStore(Mutex<HashMap<String, String>>);
+
+    impl ProjectEventStore for FakeProjectEventStore {
+        fn read(&self, key: &str) -> Option<String> {
+            self.0.lock().get(key).cloned()
+        }
+
+        fn scan(&self, prefix: &str) -> BoxFuture<'static, Result<Vec<(String, String)>>> {
+            let entries = self
+                .0
+                .lock()
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            async { Ok(entries) }.boxed()
+        }
+
+        fn write(&self, key: String, value: String) -> BoxFuture<'static, Result<()>> {
+            self.0.lock().insert(key, value);
+            async { Ok(()) }.boxed()
+        }
+
+        fn delete(&self, key: String) -> BoxFuture<'static, Result<()>> {
+            self.0.lock().remove(&key);
+            async { Ok(()) }.boxed()
+        }
+    }
+
+    #[gpui::test]
+    fn test_events_held_until_consent(cx: &mut gpui::TestAppContext) {
+        let new_telemetry = |store: Arc<FakeProjectEventStore>, cx: &mut gpui::TestAppContext| {
+            let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response())
+                .with_project_event_store(store);
+            let mut state = telemetry.state.lock();
+            state.settings.metrics = true;
+            state.settings.require_consent = true;
+            drop(state);
+            telemetry
+        };
+        let event = |event_type: &str| {
+            Event::Flexible(FlexibleEvent {
+                event_type: event_type.to_string(),
+                event_properties: HashMap::default(),
+            })
+        };
+
+        let store = Arc::new(FakeProjectEventStore::default());
+        let telemetry = new_telemetry(store.clone(), cx);
+        assert!(telemetry.needs_consent());
+        telemetry.report_event(event("First"));
+        telemetry.report_event(event("Second"));
+        assert_eq!(telemetry.stats().events_queued, 0);
+
+        telemetry.record_consent(true);
+        cx.run_until_parked();
+        assert!(!telemetry.needs_consent());
+        assert_eq!(telemetry.stats().events_queued, 2);
+        telemetry.report_event(event("Third"));
+        assert_eq!(telemetry.stats().events_queued, 3);
+        assert_eq!(
+            store.0.lock().get(CONSENT_KEY).map(String::as_str),
+            Some("granted")
+        );
+        assert!(!new_telemetry(store, cx).needs_consent());
+
+        let store = Arc::new(FakeProjectEventStore::default());
+        let telemetry = new_telemetry(store.clone(), cx);
+        telemetry.report_event(event("First"));
+        telemetry.record_consent(false);
+        cx.run_until_parked();
+        telemetry.report_event(event("Second"));
+        let stats = telemetry.stats();
+        assert_eq!(stats.events_queued, 0);
+        assert_eq!(stats.events_dropped, 2);
+        assert_eq!(
+            store.0.lock().get(CONSENT_KEY).map(String::as_str),
+            Some("denied")
+        );
+    }
+
+    #[test]
+    fn test_install_source() {
+        let direct = Path::new("/Applications/Fred.app/Contents/MacOS/fred");
+        assert_eq!(install_source(None, direct), "direct download");
+        assert_eq!(
+            install_source(Some("  homebrew-cask ".to_string()), direct),
+            "homebrew-cask"
+        );
+        assert_eq!(
+            install_source(Some(String::new()), direct),
+            "direct download"
+        );
+        assert_eq!(
+            install_source(None, Path::new("/nix/store/abc-fred-1.0/bin/fred")),
+            "nix"
+        );
+        assert_eq!(
+            install_source(None, Path::new("/usr/bin/fred")),
+            "system package"
+        );
+    }
+
+    #[gpui::test]
+    fn test_first_run_reported_once(cx: &mut gpui::TestAppContext) {
+        let store = Arc::new(FakeProjectEventStore::default());
+        let start = |cx: &mut gpui::TestAppContext, update: fn(&mut TelemetryState)| {
+            let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response())
+                .with_project_event_store(store.clone())
+                .with_memory_sink();
+            update(&mut telemetry.state.lock());
+            cx.update(|cx| {
+                telemetry.start(
+                    None,
+                    Some("installation".to_string()),
+                    "session".to_string(),
+                    cx,
+                )
+            });
+            cx.run_until_parked();
+            let first_run_events = telemetry
+                .recorded_events()
+                .iter()
+                .filter(|event| {
+                    matches!(event, Event::Flexible(event) if event.event_type == "First Run")
+                })
+                .count();
+            (first_run_events, telemetry.stats().events_queued)
+        };
+
+        // Neither a dropped event nor one awaiting consent counts as reported.
+        assert_eq!(start(cx, |state| state.settings.metrics = false), (1, 0));
+        assert_eq!(
+            start(cx, |state| {
+                state.settings.metrics = true;
+                state.settings.require_consent = true;
+            }),
+            (1, 0)
+        );
+        assert!(!store.0.lock().contains_key(FIRST_RUN_KEY));
+
+        assert_eq!(start(cx, |state| state.settings.metrics = true), (1, 1));
+        assert!(store.0.lock().contains_key(FIRST_RUN_KEY));
+        assert_eq!(start(cx, |state| state.settings.metrics = true), (0, 0));
+    }
+
+    #[gpui::test]
+    fn test_persisted_project_opened_events(cx: &mut gpui::TestAppContext) {
+        let store = Arc::new(FakeProjectEventStore::default());
+        let new_telemetry = |cx: &mut gpui::TestAppContext| {
+            let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response());
+            telemetry.state.lock().settings.persist_project_events = true;
+            telemetry
+                .with_project_event_store(store.clone())
+                .with_memory_sink()
+        };
+        let worktree_id = WorktreeId::from_usize(1);
+        let entries = added_entries(&["Gemfile"]);
+
+        let telemetry = new_telemetry(cx);
+        telemetry.report_discovered_project_type_events(
+            worktree_id,
+            Path::new("/code/app"),
+            None,
+            &entries,
+        );
+        cx.run_until_parked();
+        assert_eq!(telemetry.recorded_events().len(), 1);
+        assert_eq!(store.0.lock().len(), 1);
+
+        // A restart starts with fresh in-memory state, but the store remembers the project.
+        // Entries from earlier days are pruned when the store is loaded.
+        let stale_key = format!("{PROJECT_OPENED_KEY_PREFIX}stale");
+        store
+            .0
+            .lock()
+            .insert(stale_key.clone(), "2020-01-01".to_string());
+        let telemetry = new_telemetry(cx);
+        telemetry.report_discovered_project_type_events(
+            worktree_id,
+            Path::new("/code/app"),
+            None,
+            &entries,
+        );
+        cx.run_until_parked();
+        assert!(telemetry.recorded_events().is_empty());
+        assert!(!store.0.lock().contains_key(&stale_key));
+
+        telemetry.report_discovered_project_type_events(
+            WorktreeId::from_usize(2),
+            Path::new("/code/other"),
+            None,
+            &entries,
+        );
+        assert_eq!(telemetry.recorded_events().len(), 1);
+    }
+
+    #[gpui::test]
+    fn test_persisted_project_opened_events_reset_daily(cx: &mut gpui::TestAppContext) {
+        let store = Arc::new(FakeProjectEventStore::default());
+        let clock = Arc::new(clock::FakeSystemClock::new());
+        let before_midnight = "2024-01-01T23:59:00Z"
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap();
+        let utc_anchor = (clock.utc_now(), before_midnight);
+        let new_telemetry = |cx: &mut gpui::TestAppContext| {
+            let telemetry = test_telemetry_with_clock(
+                cx,
+                clock.clone(),
+                http_client::FakeHttpClient::with_404_response(),
+            );
+            {
+                let mut state = telemetry.state.lock();
+                state.settings.persist_project_events = true;
+                state.utc_anchor = utc_anchor;
+            }
+            telemetry
+                .with_project_event_store(store.clone())
+                .with_memory_sink()
+        };
+        let worktree_id = WorktreeId::from_usize(1);
+        let entries = added_entries(&["Gemfile"]);
+        let report = |telemetry: &Arc<Telemetry>, cx: &mut gpui::TestAppContext| {
+            telemetry.report_discovered_project_type_events(
+                worktree_id,
+                Path::new("/code/app"),
+                None,
+                &entries,
+            );
+            cx.run_until_parked();
+            telemetry.recorded_events().len()
+        };
+
+        assert_eq!(report(&new_telemetry(cx), cx), 1);
+        assert_eq!(report(&new_telemetry(cx), cx), 0);
+
+        clock.advance(Duration::from_secs(120));
+        assert_eq!(report(&new_telemetry(cx), cx), 1);
+        assert_eq!(
+            store.0.lock().values().collect::<Vec<_>>(),
+            vec!["2024-01-02"]
+        );
+    }
+
+    #[gpui::test]
+    fn test_detect_infra_project_types(cx: &mut gpui::TestAppContext) {
+        let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response());
+        let entries = added_entries(&[
+            "Dockerfile",
+            "Cargo.toml",
+            "deploy/main.tf",
+            "deploy/network.tf",
+            "deploy/docker-compose.yml",
+        ]);
+
+        assert_eq!(
+            telemetry.detect_project_types(WorktreeId::from_usize(1), &entries),
+            Some(vec![
+                ("docker", ".".to_string()),
+                ("docker", "deploy".to_string()),
+                ("rust", ".".to_string()),
+                ("terraform", "deploy".to_string()),
+            ])
+        );
+    }
+
+    #[gpui::test]
+    fn test_register_project_type_detectors(cx: &mut gpui::TestAppContext) {
+        let telemetry = test_telemetry(cx, http_client::FakeHttpClient::with_404_response());
+        telemetry.register_project_type_detectors(vec![(Regex::new(r"^.*\.zig$").unwrap(), "zig")]);
+        let entries = added_entries(&["src/main.zig", "App.csproj"]);
+
+        assert_eq!(
+            telemetry.detect_project_types(WorktreeId::from_usize(1), &entries),
+            Some(vec![
+                ("dotnet", ".".to_string()),
+                ("zig", "src".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_checksum_seed() {
+        assert_eq!(parse_checksum_seed(""), None);
+        assert_eq!(parse_checksum_seed(" \t\n"), None);
+        assert_eq!(parse_checksum_seed("seed"), Some(b"seed".to_vec()));
+    }
+
+    #[test]
+    fn test_calculate_checksum_with_seed() {
+        let checksum = calculate_checksum_with_seed(b"seed", b"{}");
+        assert_eq!(
+            checksum,
+            "4791aae418fa49a6e9d3c86938e88f34318b4a8e213f287c4adbf1584fe9f291"
+        );
+        assert!(is_well_formed_checksum(&checksum));
+
+        assert_eq!(
+            calculate_checksum_with_seed(CHECKSUM_SELF_TEST_SEED, &CHECKSUM_SELF_TEST_INPUT),
+            CHECKSUM_SELF_TEST_EXPECTED
+        );
+
+        assert!(!is_well_formed_checksum(&checksum.to_uppercase()));
+        assert!(!is_well_formed_checksum(&checksum[1..]));
+    }
+
+    #[test]
+    fn test_encode_request_body_round_trip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let json = serde_json::to_vec(&serde_json::json!({
+            "events": vec!["Editor Edited"; 200],
+        }))
+        .unwrap();
+        assert!(json.len() >= COMPRESSION_THRESHOLD_BYTES);
+
+        let (body, content_encoding) = encode_request_body(json.clone(), true).unwrap();
+        assert_eq!(content_encoding, Some("gzip"));
+        assert!(body.len() < json.len());
+
+        let mut decoded = Vec::new();
+        GzDecoder::new(body.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, json);
+
+        let (body, content_encoding) = encode_request_body(json.clone(), false).unwrap();
+        assert_eq!(content_encoding, None);
+        assert_eq!(body, json);
+
+        let small_json = br#"{"events":[]}"#.to_vec();
+        let (body, content_encoding) = encode_request_body(small_json.clone(), true).unwrap();
+        assert_eq!(content_encoding, None);
+        assert_eq!(body, small_json);
+    }
+
+    #[test]
+    fn test_rotate_log_file_if_needed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telemetry.log");
+        let backup = |index: usize| dir.path().join(format!("telemetry.log.{index}"));
+        let mut file = File::create(&path).unwrap();
+
+        file.write_all(&[b'a'; 8]).unwrap();
+        rotate_log_file_if_needed(&mut file, &path, 8, 2).unwrap();
+        assert!(!backup(1).exists());
+
+        file.write_all(b"a").unwrap();
+        rotate_log_file_if_needed(&mut file, &path, 8, 2).unwrap();
+        assert_eq!(std::fs::read(backup(1)).unwrap(), [b'a'; 9]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        file.write_all(&[b'b'; 9]).unwrap();
+        rotate_log_file_if_needed(&mut file, &path, 8, 2).unwrap();
+        assert_eq!(std::fs::read(backup(1)).unwrap(), [b'b'; 9]);
+        assert_eq!(std::fs::read(backup(2)).unwrap(), [b'a'; 9]);
+
+        file.write_all(&[b'c'; 9]).unwrap();
+        rotate_log_file_if_needed(&mut file, &path, 8, 2).unwrap();
+        assert_eq!(std::fs::read(backup(1)).unwrap(), [b'c'; 9]);
+        assert_eq!(std::fs::read(backup(2)).unwrap(), [b'b'; 9]);
+        assert!(!backup(3).exists());
+    }
+
+    #[test]
+    fn test_upload_minidumps_in_dir() {
+        use futures::AsyncReadExt as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("uploaded.dmp"), b"ok").unwrap();
+        std::fs::write(dir.path().join("failed.dmp"), b"fail").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"ok").unwrap();
+        // Deleted while it's being uploaded, so that deleting it afterwards fails.
+        std::fs::write(dir.path().join("deleted.dmp"), b"deleted").unwrap();
+        let http_client = http_client::FakeHttpClient::create({
+            let deleted_path = dir.path().join("deleted.dmp");
+            move |request| {
+                let deleted_path = deleted_path.clone();
+                async move {
+                    let mut body = Vec::new();
+                    request.into_body().read_to_end(&mut body).await?;
+                    if body == b"deleted" {
+                        std::fs::remove_file(&deleted_path)?;
+                    }
+                    let status = if body == b"fail" { 500 } else { 200 };
+                    Ok(http_client::Response::builder()
+                        .status(status)
+                        .body(AsyncBody::default())?)
+                }
+            }
+        });
+
+        let uploaded = smol::block_on(upload_minidumps_in_dir(
+            &http_client,
+            "https://crashes.example/minidump",
+            dir.path(),
+        ))
+        .unwrap();
+        assert_eq!(uploaded, 2);
+        assert!(!dir.path().join("uploaded.dmp").exists());
+        assert!(dir.path().join("failed.dmp").exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_clear_log_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telemetry.log");
+        let backup = |index: usize| dir.path().join(format!("telemetry.log.{index}"));
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"event\n").unwrap();
+        std::fs::write(backup(1), b"older").unwrap();
+        std::fs::write(backup(2), b"oldest").unwrap();
+
+        clear_log_files(Some(&mut file), &path, 2).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        assert!(!backup(1).exists());
+        assert!(!backup(2).exists());
+
+        file.write_all(b"event\n").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"event\n");
+
+        clear_log_files(None, &path, 2).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        clear_log_files(None, &dir.path().join("missing.log"), 2).unwrap();
+        assert!(!dir.path().join("missing.log").exists());
+    }
 }
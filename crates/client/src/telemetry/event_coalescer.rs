@@ -2,9 +2,11 @@ use std::time;
 use std::{sync::Arc, time::Instant};
 
 use clock::SystemClock;
+use telemetry_events::AssistantEventData;
 
 const COALESCE_TIMEOUT: time::Duration = time::Duration::from_secs(20);
 const SIMULATED_DURATION_FOR_SINGLE_EVENT: time::Duration = time::Duration::from_millis(1);
+const ASSISTANT_RESPONSE_COALESCE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 
 #[derive(Debug, PartialEq)]
 struct PeriodData {
@@ -64,11 +66,86 @@ impl EventCoalescer {
 
         None
     }
+
+    /// Ends the in-progress period, if any, so it can be reported without waiting for another event.
+    pub fn close_current_period(&mut self) -> Option<(Instant, Instant, &'static str)> {
+        let state = self.state.take()?;
+        let period_end = state
+            .end
+            .unwrap_or(state.start + SIMULATED_DURATION_FOR_SINGLE_EVENT);
+        Some((state.start, period_end, state.environment))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AssistantResponsePeriod {
+    /// The first response of the period, used for the fields shared by all of its responses.
+    /// Token usage and cost are summed across the responses that reported them, and stay `None`
+    /// when none did.
+    pub event: AssistantEventData,
+    pub count: usize,
+    pub total_latency: time::Duration,
+    last_response: Instant,
+}
+
+/// Groups consecutive assistant responses for the same conversation and model into a single period.
+pub struct AssistantResponseCoalescer {
+    clock: Arc<dyn SystemClock>,
+    state: Option<AssistantResponsePeriod>,
+}
+
+impl AssistantResponseCoalescer {
+    pub fn new(clock: Arc<dyn SystemClock>) -> Self {
+        Self { clock, state: None }
+    }
+
+    pub fn log_response(&mut self, event: AssistantEventData) -> Option<AssistantResponsePeriod> {
+        let log_time = self.clock.utc_now();
+        let latency = event.response_latency.unwrap_or_default();
+
+        if let Some(state) = &mut self.state {
+            let within_timeout = log_time.saturating_duration_since(state.last_response)
+                < ASSISTANT_RESPONSE_COALESCE_TIMEOUT;
+            let is_same_session = state.event.conversation_id == event.conversation_id
+                && state.event.kind == event.kind
+                && state.event.model == event.model;
+
+            if within_timeout && is_same_session {
+                state.count += 1;
+                state.total_latency += latency;
+                state.event.input_tokens = sum_usage(state.event.input_tokens, event.input_tokens);
+                state.event.output_tokens =
+                    sum_usage(state.event.output_tokens, event.output_tokens);
+                state.event.cost_usd = sum_usage(state.event.cost_usd, event.cost_usd);
+                state.last_response = log_time;
+                return None;
+            }
+        }
+
+        self.state.replace(AssistantResponsePeriod {
+            event,
+            count: 1,
+            total_latency: latency,
+            last_response: log_time,
+        })
+    }
+
+    pub fn flush(&mut self) -> Option<AssistantResponsePeriod> {
+        self.state.take()
+    }
+}
+
+fn sum_usage<T: std::ops::Add<Output = T>>(total: Option<T>, value: Option<T>) -> Option<T> {
+    match (total, value) {
+        (Some(total), Some(value)) => Some(total + value),
+        (total, value) => total.or(value),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use clock::FakeSystemClock;
+    use telemetry_events::{AssistantKind, AssistantPhase};
 
     use super::*;
 
@@ -280,4 +357,119 @@ mod tests {
     //          |-------------------|
     // |period_start                |period_end
     //                                                   |new_period_start
+
+    #[test]
+    fn test_close_current_period() {
+        let clock = Arc::new(FakeSystemClock::new());
+        let environment_1 = "environment_1";
+        let mut event_coalescer = EventCoalescer::new(clock.clone());
+
+        assert_eq!(event_coalescer.close_current_period(), None);
+
+        let period_start = clock.utc_now();
+        event_coalescer.log_event(environment_1);
+        assert_eq!(
+            event_coalescer.close_current_period(),
+            Some((
+                period_start,
+                period_start + SIMULATED_DURATION_FOR_SINGLE_EVENT,
+                environment_1
+            ))
+        );
+        assert_eq!(event_coalescer.state, None);
+
+        let period_start = clock.utc_now();
+        event_coalescer.log_event(environment_1);
+        clock.advance(COALESCE_TIMEOUT / 2);
+        let period_end = clock.utc_now();
+        event_coalescer.log_event(environment_1);
+        assert_eq!(
+            event_coalescer.close_current_period(),
+            Some((period_start, period_end, environment_1))
+        );
+        assert_eq!(event_coalescer.close_current_period(), None);
+    }
+
+    fn assistant_response(conversation_id: &str, latency_ms: u64) -> AssistantEventData {
+        AssistantEventData {
+            conversation_id: Some(conversation_id.to_string()),
+            message_id: None,
+            kind: AssistantKind::Panel,
+            phase: AssistantPhase::Response,
+            model: "model".to_string(),
+            model_provider: "provider".to_string(),
+            response_latency: Some(time::Duration::from_millis(latency_ms)),
+            error_message: None,
+            language_name: None,
+            input_tokens: None,
+            output_tokens: None,
+            cost_usd: None,
+        }
+    }
+
+    #[test]
+    fn test_assistant_responses_coalesce_within_timeout() {
+        let clock = Arc::new(FakeSystemClock::new());
+        let mut coalescer = AssistantResponseCoalescer::new(clock.clone());
+
+        assert_eq!(coalescer.log_response(assistant_response("a", 100)), None);
+
+        for _ in 0..3 {
+            clock.advance(ASSISTANT_RESPONSE_COALESCE_TIMEOUT / 2);
+            assert_eq!(coalescer.log_response(assistant_response("a", 100)), None);
+        }
+        let last_response_time = clock.utc_now();
+
+        // A response from a different conversation closes the current period
+        let period = coalescer.log_response(assistant_response("b", 50));
+        assert_eq!(
+            period,
+            Some(AssistantResponsePeriod {
+                event: assistant_response("a", 100),
+                count: 4,
+                total_latency: time::Duration::from_millis(400),
+                last_response: last_response_time,
+            })
+        );
+
+        clock.advance(ASSISTANT_RESPONSE_COALESCE_TIMEOUT * 2);
+        let period = coalescer.log_response(assistant_response("b", 50));
+        assert_eq!(
+            period,
+            Some(AssistantResponsePeriod {
+                event: assistant_response("b", 50),
+                count: 1,
+                total_latency: time::Duration::from_millis(50),
+                last_response: last_response_time,
+            })
+        );
+
+        assert_eq!(coalescer.flush().map(|period| period.count), Some(1));
+        assert_eq!(coalescer.flush(), None);
+    }
+
+    #[test]
+    fn test_assistant_response_usage_is_summed() {
+        let clock = Arc::new(FakeSystemClock::new());
+        let mut coalescer = AssistantResponseCoalescer::new(clock.clone());
+        let with_usage = |input_tokens, output_tokens, cost_usd| AssistantEventData {
+            input_tokens: Some(input_tokens),
+            output_tokens: Some(output_tokens),
+            cost_usd,
+            ..assistant_response("a", 100)
+        };
+
+        coalescer.log_response(with_usage(10, 20, Some(0.25)));
+        coalescer.log_response(assistant_response("a", 100));
+        coalescer.log_response(with_usage(5, 7, None));
+        coalescer.log_response(with_usage(1, 1, Some(0.5)));
+
+        let event = coalescer.flush().unwrap().event;
+        assert_eq!(event.input_tokens, Some(16));
+        assert_eq!(event.output_tokens, Some(28));
+        assert_eq!(event.cost_usd, Some(0.75));
+
+        coalescer.log_response(with_usage(1, 1, None));
+        assert_eq!(coalescer.flush().unwrap().event.cost_usd, None);
+    }
 }
@@ -1,8 +1,14 @@
-use std::time::Instant;
+use std::{future::Future, pin::Pin, time::Instant};
 
 pub trait SystemClock: Send + Sync {
     /// Returns the current date and time in UTC.
     fn utc_now(&self) -> Instant;
+
+    /// Returns a future that completes once this clock reaches `deadline`, for clocks that are
+    /// advanced manually. Returns `None` for real clocks, whose callers should use a real timer.
+    fn wait_until(&self, _deadline: Instant) -> Option<Pin<Box<dyn Future<Output = ()> + Send>>> {
+        None
+    }
 }
 
 pub struct RealSystemClock;
@@ -16,12 +22,13 @@ impl SystemClock for RealSystemClock {
 #[cfg(any(test, feature = "test-support"))]
 pub struct FakeSystemClockState {
     now: Instant,
+    waiters: Vec<std::task::Waker>,
 }
 
 #[cfg(any(test, feature = "test-support"))]
 pub struct FakeSystemClock {
     // Use an unfair lock to ensure tests are deterministic.
-    state: parking_lot::Mutex<FakeSystemClockState>,
+    state: std::sync::Arc<parking_lot::Mutex<FakeSystemClockState>>,
 }
 
 #[cfg(any(test, feature = "test-support"))]
@@ -29,19 +36,29 @@ impl FakeSystemClock {
     pub fn new() -> Self {
         let state = FakeSystemClockState {
             now: Instant::now(),
+            waiters: Vec::new(),
         };
 
         Self {
-            state: parking_lot::Mutex::new(state),
+            state: std::sync::Arc::new(parking_lot::Mutex::new(state)),
         }
     }
 
     pub fn set_now(&self, now: Instant) {
         self.state.lock().now = now;
+        self.wake_waiters();
     }
 
     pub fn advance(&self, duration: std::time::Duration) {
         self.state.lock().now += duration;
+        self.wake_waiters();
+    }
+
+    fn wake_waiters(&self) {
+        let waiters = std::mem::take(&mut self.state.lock().waiters);
+        for waiter in waiters {
+            waiter.wake();
+        }
     }
 }
 
@@ -50,4 +67,17 @@ impl SystemClock for FakeSystemClock {
     fn utc_now(&self) -> Instant {
         self.state.lock().now
     }
+
+    fn wait_until(&self, deadline: Instant) -> Option<Pin<Box<dyn Future<Output = ()> + Send>>> {
+        let state = self.state.clone();
+        Some(Box::pin(std::future::poll_fn(move |cx| {
+            let mut state = state.lock();
+            if state.now >= deadline {
+                std::task::Poll::Ready(())
+            } else {
+                state.waiters.push(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        })))
+    }
 }
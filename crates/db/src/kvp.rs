@@ -59,6 +59,12 @@ impl KeyValueStore {
             DELETE FROM kv_store WHERE key = (?)
         }
     }
+
+    query! {
+        pub async fn scan_kvp(prefix: String) -> Result<Vec<(String, String)>> {
+            SELECT key, value FROM kv_store WHERE instr(key, (?)) = 1
+        }
+    }
 }
 
 #[cfg(test)]
@@ -88,6 +94,17 @@ mod tests {
 
         db.delete_kvp("key-1".to_string()).await.unwrap();
         assert_eq!(db.read_kvp("key-1").unwrap(), None);
+
+        db.write_kvp("prefix_a".to_string(), "a".to_string())
+            .await
+            .unwrap();
+        db.write_kvp("prefixed".to_string(), "b".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            db.scan_kvp("prefix_".to_string()).await.unwrap(),
+            [("prefix_a".to_string(), "a".to_string())]
+        );
     }
 }
 
@@ -19940,14 +19940,20 @@ impl Editor {
                 }
 
                 let Some(project) = &self.project else { return };
-                let (telemetry, is_via_ssh) = {
+                let (telemetry, is_via_ssh, git_branch) = {
                     let project = project.read(cx);
                     let telemetry = project.client().telemetry().clone();
                     let is_via_ssh = project.is_via_ssh();
-                    (telemetry, is_via_ssh)
+                    let git_branch = edited_buffer
+                        .as_ref()
+                        .filter(|_| telemetry.include_git_branch())
+                        .and_then(|buffer| {
+                            project.git_branch_for_buffer(buffer.read(cx).remote_id(), cx)
+                        });
+                    (telemetry, is_via_ssh, git_branch)
                 };
                 refresh_linked_ranges(self, window, cx);
-                telemetry.log_edit_event("editor", is_via_ssh);
+                telemetry.log_edit_event("editor", is_via_ssh, git_branch);
             }
             multi_buffer::Event::ExcerptsAdded {
                 buffer,
@@ -316,7 +316,7 @@ fn main() {
                 &run_dir,
             )?;
 
-            app_state.client.telemetry().flush_events().await;
+            app_state.client.telemetry().flush_events().await.log_err();
 
             cx.update(|cx| cx.quit())
         })
@@ -3086,6 +3086,7 @@ impl Project {
                 cx.emit(Event::WorktreeAdded(worktree.read(cx).id()));
             }
             WorktreeStoreEvent::WorktreeRemoved(_, id) => {
+                self.client().telemetry().report_project_closed(*id);
                 cx.emit(Event::WorktreeRemoved(*id));
             }
             WorktreeStoreEvent::WorktreeReleased(_, id) => {
@@ -3094,9 +3095,20 @@ impl Project {
             WorktreeStoreEvent::WorktreeOrderChanged => cx.emit(Event::WorktreeOrderChanged),
             WorktreeStoreEvent::WorktreeUpdateSent(_) => {}
             WorktreeStoreEvent::WorktreeUpdatedEntries(worktree_id, changes) => {
-                self.client()
-                    .telemetry()
-                    .report_discovered_project_type_events(*worktree_id, changes);
+                if let Some(worktree) = self.worktree_for_id(*worktree_id, cx) {
+                    let telemetry = self.client().telemetry().clone();
+                    let abs_path = worktree.read(cx).abs_path();
+                    let git_branch = telemetry
+                        .include_git_branch()
+                        .then(|| self.git_branch_for_abs_path(&abs_path, cx))
+                        .flatten();
+                    telemetry.report_discovered_project_type_events(
+                        *worktree_id,
+                        &abs_path,
+                        git_branch.as_deref(),
+                        changes,
+                    );
+                }
                 cx.emit(Event::WorktreeUpdatedEntries(*worktree_id, changes.clone()))
             }
             WorktreeStoreEvent::WorktreeDeletedEntry(worktree_id, id) => {
@@ -5116,6 +5128,41 @@ impl Project {
         self.git_store.read(cx).repositories()
     }
 
+    /// The branch checked out in the innermost repository containing `abs_path`.
+    pub fn git_branch_for_abs_path(&self, abs_path: &Path, cx: &App) -> Option<String> {
+        self.repositories(cx)
+            .values()
+            .map(|repository| repository.read(cx))
+            .filter(|repository| abs_path.starts_with(&repository.work_directory_abs_path))
+            .max_by_key(|repository| repository.work_directory_abs_path.clone())?
+            .branch
+            .as_ref()
+            .map(|branch| branch.name().to_string())
+    }
+
+    /// The branch checked out in the repository shown in the git panel.
+    pub fn active_git_branch(&self, cx: &App) -> Option<String> {
+        let repository = self.active_repository(cx)?;
+        repository
+            .read(cx)
+            .branch
+            .as_ref()
+            .map(|branch| branch.name().to_string())
+    }
+
+    /// The branch checked out in the repository containing `buffer_id`'s file.
+    pub fn git_branch_for_buffer(&self, buffer_id: BufferId, cx: &App) -> Option<String> {
+        let (repository, _) = self
+            .git_store
+            .read(cx)
+            .repository_and_path_for_buffer_id(buffer_id, cx)?;
+        repository
+            .read(cx)
+            .branch
+            .as_ref()
+            .map(|branch| branch.name().to_string())
+    }
+
     pub fn status_for_buffer_id(&self, buffer_id: BufferId, cx: &App) -> Option<FileStatus> {
         self.git_store.read(cx).status_for_buffer_id(buffer_id, cx)
     }
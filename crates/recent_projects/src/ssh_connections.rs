@@ -2,10 +2,11 @@ use std::collections::BTreeSet;
 use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::{Context as _, Result};
-use auto_update::AutoUpdater;
+use auto_update::{AutoUpdater, RemoteReleaseError};
 use editor::Editor;
 use extension_host::ExtensionStore;
-use futures::channel::oneshot;
+use futures::StreamExt as _;
+use futures::channel::{mpsc, oneshot};
 use gpui::{
     Animation, AnimationExt, AnyWindowHandle, App, AsyncApp, DismissEvent, Entity, EventEmitter,
     Focusable, FontFeatures, ParentElement as _, PromptLevel, Render, SemanticVersion,
@@ -16,7 +17,7 @@ use language::CursorShape;
 use markdown::{Markdown, MarkdownElement, MarkdownStyle};
 use release_channel::ReleaseChannel;
 use remote::ssh_session::{ConnectionIdentifier, SshPortForwardOption};
-use remote::{SshConnectionOptions, SshPlatform, SshRemoteClient};
+use remote::{RemoteServerRelease, SshConnectionOptions, SshPlatform, SshRemoteClient};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsSources};
@@ -477,27 +478,51 @@ impl remote::SshClientDelegate for SshClientDelegate {
         release_channel: ReleaseChannel,
         version: Option<SemanticVersion>,
         cx: &mut AsyncApp,
-    ) -> Task<anyhow::Result<PathBuf>> {
+    ) -> Task<anyhow::Result<RemoteServerRelease>> {
+        let delegate = self.clone();
         cx.spawn(async move |cx| {
-            let binary_path = AutoUpdater::download_remote_server_release(
+            let (progress_tx, mut progress_rx) = mpsc::unbounded();
+            cx.spawn(async move |cx| {
+                while let Some((bytes_downloaded, total_bytes)) = progress_rx.next().await {
+                    let status = download_progress_status(bytes_downloaded, total_bytes);
+                    delegate.update_status(Some(&status), cx);
+                }
+            })
+            .detach();
+
+            let release = AutoUpdater::download_remote_server_release(
                 platform.os,
                 platform.arch,
                 release_channel,
                 version,
+                move |bytes_downloaded, total_bytes| {
+                    progress_tx
+                        .unbounded_send((bytes_downloaded, total_bytes))
+                        .ok();
+                },
                 cx,
             )
-            .await
-            .with_context(|| {
-                format!(
-                    "Downloading remote server binary (version: {}, os: {}, arch: {})",
-                    version
-                        .map(|v| format!("{}", v))
-                        .unwrap_or("unknown".to_string()),
-                    platform.os,
-                    platform.arch,
-                )
-            })?;
-            Ok(binary_path)
+            .await;
+            match release {
+                // Nothing was downloaded, so the download details would only obscure the message.
+                Err(error @ RemoteReleaseError::Unsupported(_)) => Err(error.into()),
+                release => async {
+                    let release = release?;
+                    AutoUpdater::verify_remote_server_release(&release).await?;
+                    anyhow::Ok(release)
+                }
+                .await
+                .with_context(|| {
+                    format!(
+                        "Downloading remote server binary (version: {}, os: {}, arch: {})",
+                        version
+                            .map(|v| format!("{}", v))
+                            .unwrap_or("unknown".to_string()),
+                        platform.os,
+                        platform.arch,
+                    )
+                }),
+            }
         })
     }
 
@@ -507,7 +532,7 @@ impl remote::SshClientDelegate for SshClientDelegate {
         release_channel: ReleaseChannel,
         version: Option<SemanticVersion>,
         cx: &mut AsyncApp,
-    ) -> Task<Result<Option<(String, String)>>> {
+    ) -> Task<Result<Option<(String, String, Option<String>)>>> {
         cx.spawn(async move |cx| {
             AutoUpdater::get_remote_server_release_url(
                 platform.os,
@@ -533,6 +558,19 @@ impl SshClientDelegate {
     }
 }
 
+fn download_progress_status(bytes_downloaded: u64, total_bytes: Option<u64>) -> String {
+    match total_bytes {
+        Some(total_bytes) if total_bytes > 0 => format!(
+            "Downloading remote server… {:.0}%",
+            (bytes_downloaded as f64 / total_bytes as f64).min(1.0) * 100.0
+        ),
+        _ => format!(
+            "Downloading remote server… {}",
+            util::size::format_file_size(bytes_downloaded, true)
+        ),
+    }
+}
+
 pub fn is_connecting_over_ssh(workspace: &Workspace, cx: &App) -> bool {
     workspace.active_modal::<SshConnectionModal>(cx).is_some()
 }
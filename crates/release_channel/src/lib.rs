@@ -138,6 +138,11 @@ impl ReleaseChannel {
             .map(|channel| channel.0)
     }
 
+    /// Sets the global [`ReleaseChannel`].
+    pub fn set_global(channel: ReleaseChannel, cx: &mut App) {
+        cx.set_global(GlobalReleaseChannel(channel))
+    }
+
     /// Returns whether we want to poll for updates for this [`ReleaseChannel`]
     pub fn poll_for_updates(&self) -> bool {
         !matches!(self, ReleaseChannel::Dev)
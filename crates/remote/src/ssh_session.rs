@@ -308,6 +308,17 @@ impl SshConnectionOptions {
     }
 }
 
+/// A remote server binary that has been made available locally, ready to be uploaded.
+#[derive(Clone, Debug)]
+pub struct RemoteServerRelease {
+    pub path: PathBuf,
+    pub version: SemanticVersion,
+    /// Whether the binary was already present locally rather than freshly downloaded.
+    pub from_cache: bool,
+    /// Hex-encoded SHA-256 the binary must match, when the release server provides one.
+    pub sha256: Option<String>,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct SshPlatform {
     pub os: &'static str,
@@ -322,7 +333,7 @@ pub trait SshClientDelegate: Send + Sync {
         release_channel: ReleaseChannel,
         version: Option<SemanticVersion>,
         cx: &mut AsyncApp,
-    ) -> Task<Result<Option<(String, String)>>>;
+    ) -> Task<Result<Option<(String, String, Option<String>)>>>;
 
     fn download_server_binary_locally(
         &self,
@@ -330,7 +341,7 @@ pub trait SshClientDelegate: Send + Sync {
         release_channel: ReleaseChannel,
         version: Option<SemanticVersion>,
         cx: &mut AsyncApp,
-    ) -> Task<Result<PathBuf>>;
+    ) -> Task<Result<RemoteServerRelease>>;
     fn set_status(&self, status: Option<&str>, cx: &mut AsyncApp);
 }
 
@@ -1835,11 +1846,16 @@ impl SshRemoteConnection {
             self.ssh_path_style,
         );
         if !self.socket.connection_options.upload_binary_over_ssh {
-            if let Some((url, body)) = delegate
+            match delegate
                 .get_download_params(self.ssh_platform, release_channel, wanted_version, cx)
                 .await?
             {
-                match self
+                // The host can't be relied on to have a tool to verify the checksum with, so
+                // checksummed binaries are downloaded locally and verified before being uploaded.
+                Some((_, _, Some(_))) => {
+                    log::info!("downloading checksummed remote server binary locally")
+                }
+                Some((url, body, None)) => match self
                     .download_binary_on_server(&url, &body, &tmp_path_gz, delegate, cx)
                     .await
                 {
@@ -1854,14 +1870,25 @@ impl SshRemoteConnection {
                             e
                         )
                     }
-                }
+                },
+                None => {}
             }
         }
 
-        let src_path = delegate
+        let release = delegate
             .download_server_binary_locally(self.ssh_platform, release_channel, wanted_version, cx)
             .await?;
-        self.upload_local_server_binary(&src_path, &tmp_path_gz, delegate, cx)
+        log::info!(
+            "using {} remote server binary {} at {:?}",
+            if release.from_cache {
+                "cached"
+            } else {
+                "downloaded"
+            },
+            release.version,
+            release.path
+        );
+        self.upload_local_server_binary(&release.path, &tmp_path_gz, delegate, cx)
             .await?;
         self.extract_server_binary(&dst_path, &tmp_path_gz, delegate, cx)
             .await?;
@@ -2703,7 +2730,7 @@ mod fake {
             _: ReleaseChannel,
             _: Option<SemanticVersion>,
             _: &mut AsyncApp,
-        ) -> Task<Result<PathBuf>> {
+        ) -> Task<Result<RemoteServerRelease>> {
             unreachable!()
         }
 
@@ -2713,7 +2740,7 @@ mod fake {
             _release_channel: ReleaseChannel,
             _version: Option<SemanticVersion>,
             _cx: &mut AsyncApp,
-        ) -> Task<Result<Option<(String, String)>>> {
+        ) -> Task<Result<Option<(String, String, Option<String>)>>> {
             unreachable!()
         }
 
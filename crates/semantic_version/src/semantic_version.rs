@@ -50,8 +50,14 @@ impl SemanticVersion {
 impl FromStr for SemanticVersion {
     type Err = anyhow::Error;
 
+    /// Parses `major.minor.patch`, ignoring any `+` build metadata, which semver says has no
+    /// bearing on precedence.
     fn from_str(s: &str) -> Result<Self> {
-        let mut components = s.trim().split('.');
+        let s = s.trim();
+        let version = s
+            .split_once('+')
+            .map_or(s, |(version, _build_metadata)| version);
+        let mut components = version.split('.');
         let major = components
             .next()
             .context("missing major version number")?
@@ -12,6 +12,7 @@ pub struct EventRequestBody {
     pub installation_id: Option<String>,
     /// Identifier unique to each logged in Zed user (randomly generated on first sign in)
     /// Identifier unique to each Zed session (differs for each time you open Zed)
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,
     pub metrics_id: Option<String>,
     /// True for Zed staff, otherwise false
@@ -24,20 +25,178 @@ pub struct EventRequestBody {
     pub architecture: String,
     /// Zed release channel (stable, preview, dev)
     pub release_channel: Option<String>,
+    /// Tag from the `environment_tag` setting, for telling apart events from e.g. CI and
+    /// staging machines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_tag: Option<String>,
     pub events: Vec<EventWrapper>,
 }
 
 impl EventRequestBody {
+    pub fn builder() -> EventRequestBodyBuilder {
+        EventRequestBodyBuilder::default()
+    }
+
     pub fn semver(&self) -> Option<SemanticVersion> {
         self.app_version.parse().ok()
     }
 }
 
+/// Builds an [`EventRequestBody`], checking that the fields the collector relies on are present.
+///
+/// A body without an installation ID is only accepted when it is explicitly marked
+/// [`anonymous`](Self::anonymous), so that events recorded before telemetry was started are
+/// caught rather than sent unattributed.
+#[derive(Debug, Default, Clone)]
+pub struct EventRequestBodyBuilder {
+    system_id: Option<String>,
+    installation_id: Option<String>,
+    anonymous: bool,
+    session_id: Option<String>,
+    metrics_id: Option<String>,
+    is_staff: Option<bool>,
+    app_version: Option<String>,
+    os_name: Option<String>,
+    os_version: Option<String>,
+    architecture: Option<String>,
+    release_channel: Option<String>,
+    environment_tag: Option<String>,
+    events: Vec<EventWrapper>,
+}
+
+impl EventRequestBodyBuilder {
+    pub fn system_id(mut self, system_id: Option<String>) -> Self {
+        self.system_id = system_id;
+        self
+    }
+
+    pub fn installation_id(mut self, installation_id: Option<String>) -> Self {
+        self.installation_id = installation_id;
+        self
+    }
+
+    /// Allows the body to be built without an installation ID.
+    pub fn anonymous(mut self, anonymous: bool) -> Self {
+        self.anonymous = anonymous;
+        self
+    }
+
+    pub fn session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    pub fn metrics_id(mut self, metrics_id: Option<String>) -> Self {
+        self.metrics_id = metrics_id;
+        self
+    }
+
+    pub fn is_staff(mut self, is_staff: Option<bool>) -> Self {
+        self.is_staff = is_staff;
+        self
+    }
+
+    pub fn app_version(mut self, app_version: impl Into<String>) -> Self {
+        self.app_version = Some(app_version.into());
+        self
+    }
+
+    pub fn os_name(mut self, os_name: impl Into<String>) -> Self {
+        self.os_name = Some(os_name.into());
+        self
+    }
+
+    pub fn os_version(mut self, os_version: Option<String>) -> Self {
+        self.os_version = os_version;
+        self
+    }
+
+    /// Defaults to the architecture this binary was built for.
+    pub fn architecture(mut self, architecture: impl Into<String>) -> Self {
+        self.architecture = Some(architecture.into());
+        self
+    }
+
+    pub fn release_channel(mut self, release_channel: Option<String>) -> Self {
+        self.release_channel = release_channel;
+        self
+    }
+
+    pub fn environment_tag(mut self, environment_tag: Option<String>) -> Self {
+        self.environment_tag = environment_tag;
+        self
+    }
+
+    pub fn events(mut self, events: Vec<EventWrapper>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn build(self) -> Result<EventRequestBody, MissingFieldsError> {
+        let is_missing = |value: &Option<String>| value.as_deref().is_none_or(str::is_empty);
+        let mut missing_fields = Vec::new();
+        if is_missing(&self.installation_id) && !self.anonymous {
+            missing_fields.push("installation_id");
+        }
+        if is_missing(&self.app_version) {
+            missing_fields.push("app_version");
+        }
+        if is_missing(&self.os_name) {
+            missing_fields.push("os_name");
+        }
+        if !missing_fields.is_empty() {
+            return Err(MissingFieldsError { missing_fields });
+        }
+
+        Ok(EventRequestBody {
+            system_id: self.system_id,
+            installation_id: self.installation_id,
+            session_id: self.session_id,
+            metrics_id: self.metrics_id,
+            is_staff: self.is_staff,
+            app_version: self.app_version.unwrap_or_default(),
+            os_name: self.os_name.unwrap_or_default(),
+            os_version: self.os_version,
+            architecture: self
+                .architecture
+                .unwrap_or_else(|| std::env::consts::ARCH.to_string()),
+            release_channel: self.release_channel,
+            environment_tag: self.environment_tag,
+            events: self.events,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFieldsError {
+    pub missing_fields: Vec<&'static str>,
+}
+
+impl Display for MissingFieldsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "event request body is missing required fields: {}",
+            self.missing_fields.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for MissingFieldsError {}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EventWrapper {
     pub signed_in: bool,
     /// Duration between this event's timestamp and the timestamp of the first event in the current batch
     pub milliseconds_since_first_event: i64,
+    /// True when the clock had moved backward since the previous event, in which case the
+    /// event was given the previous event's timestamp instead.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clock_moved_backward: bool,
+    /// Tag from the `environment_tag` setting when the event was recorded. This is unrelated
+    /// to the `environment` of edit events, which describes the file being edited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_tag: Option<String>,
     /// The event itself
     #[serde(flatten)]
     pub event: Event,
@@ -178,6 +337,15 @@ pub struct AssistantEventData {
     pub response_latency: Option<Duration>,
     pub error_message: Option<String>,
     pub language_name: Option<String>,
+    /// Tokens sent to the model, when the provider reports usage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    /// Tokens generated by the model, when the provider reports usage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+    /// Estimated cost of the request in US dollars, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -1406,7 +1406,11 @@ impl InputHandler for TerminalInputHandler {
                 window.invalidate_character_coordinates();
                 let project = this.project().read(cx);
                 let telemetry = project.client().telemetry().clone();
-                telemetry.log_edit_event("terminal", project.is_via_ssh());
+                let git_branch = telemetry
+                    .include_git_branch()
+                    .then(|| project.active_git_branch(cx))
+                    .flatten();
+                telemetry.log_edit_event("terminal", project.is_via_ssh(), git_branch);
             })
             .ok();
     }
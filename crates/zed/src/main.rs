@@ -211,6 +211,10 @@ pub fn main() {
     if is_first_instance && auto_update::check_pending_installation() {
         return;
     }
+    #[cfg(target_os = "freebsd")]
+    if crate::zed::is_first_instance() && auto_update::check_pending_installation() {
+        return;
+    }
 
     if args.dump_all_actions {
         dump_all_gpui_actions();
@@ -542,7 +546,7 @@ pub fn main() {
         });
         AppState::set_global(Arc::downgrade(&app_state), cx);
 
-        auto_update::init(client.http_client(), cx);
+        auto_update::init(client.http_client(), None, cx);
         dap_adapters::init(cx);
         auto_update_ui::init(cx);
         reliability::init(
@@ -301,7 +301,7 @@ pub fn monitor_main_thread_hangs(
 
     let foreground_executor = cx.foreground_executor();
     let background_executor = cx.background_executor();
-    let telemetry_settings = *client::TelemetrySettings::get_global(cx);
+    let telemetry_settings = client::TelemetrySettings::get_global(cx).clone();
 
     // Initialize SIGUSR2 handler to send a backtrace to a channel.
     let (backtrace_tx, backtrace_rx) = mpsc::channel();
@@ -170,6 +170,12 @@ pub fn init(cx: &mut App) {
             open_telemetry_log_file(workspace, window, cx);
         });
     });
+    cx.on_action(|_: &zed_actions::ClearTelemetryLog, cx| {
+        client::Client::global(cx)
+            .telemetry()
+            .clear_log()
+            .detach_and_log_err(cx);
+    });
     cx.on_action(|&zed_actions::OpenKeymap, cx| {
         with_active_or_new_workspace(cx, |_, window, cx| {
             open_settings_file(
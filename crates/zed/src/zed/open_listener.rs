@@ -173,6 +173,17 @@ impl OpenListener {
     }
 }
 
+/// Whether no other instance is listening for CLI connections, checked before
+/// [`listen_for_cli_connections`] claims the socket.
+#[cfg(target_os = "freebsd")]
+pub fn is_first_instance() -> bool {
+    use release_channel::RELEASE_CHANNEL_NAME;
+    use std::os::unix::net::UnixDatagram;
+
+    let sock_path = paths::data_dir().join(format!("zed-{}.sock", *RELEASE_CHANNEL_NAME));
+    UnixDatagram::unbound().is_ok_and(|socket| socket.connect(&sock_path).is_err())
+}
+
 #[cfg(any(target_os = "linux", target_os = "freebsd"))]
 pub fn listen_for_cli_connections(opener: OpenListener) -> Result<()> {
     use release_channel::RELEASE_CHANNEL_NAME;
@@ -50,6 +50,8 @@ actions!(
         OpenLicenses,
         /// Opens the telemetry log.
         OpenTelemetryLog,
+        /// Deletes the locally stored telemetry log.
+        ClearTelemetryLog,
     ]
 );
 